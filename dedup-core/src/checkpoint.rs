@@ -0,0 +1,491 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
+use serde::{Serialize, Deserialize};
+use crate::cancel::CancellationToken;
+use crate::config::{ScanConfig, ScanFingerprint};
+use crate::duplicates::{DuplicateFinder, DuplicateReport, HashStore};
+use crate::error::{ScanError, ScanStage};
+use crate::events::EventBus;
+use crate::io_scheduler::IoScheduler;
+use crate::reference::ReferenceSet;
+use crate::models::{FileHash, FileMetadata, HashAlgorithm, ProgressUpdate, ScanStats};
+use crate::walker::{ParallelWalker, WalkObserver};
+
+const CHECKPOINT_VERSION: u32 = 1;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where in the pipeline a checkpoint was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PipelineStage {
+    Walking,
+    Hashing,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct StoredHashes {
+    size: u64,
+    modified: Option<SystemTime>,
+    prefix: Option<FileHash>,
+    full: Option<FileHash>,
+}
+
+/// On-disk state of an interrupted run: the directories still to be read, the metadata
+/// collected so far and every hash already computed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub version: u32,
+    pub fingerprint: ScanFingerprint,
+    pub algorithm: HashAlgorithm,
+    pub stage: PipelineStage,
+    /// Directories that were queued but not fully read, with their walk depth.
+    pub pending_dirs: BTreeMap<PathBuf, usize>,
+    pub files: Vec<FileMetadata>,
+    pub errors: Vec<ScanError>,
+    hashes: HashMap<PathBuf, StoredHashes>,
+}
+
+impl Checkpoint {
+    pub fn new(config: &ScanConfig, algorithm: HashAlgorithm) -> Self {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            fingerprint: config.fingerprint(),
+            algorithm,
+            stage: PipelineStage::Walking,
+            pending_dirs: config.walk_roots().map(|root| (root.clone(), 0)).collect(),
+            files: Vec::new(),
+            errors: Vec::new(),
+            hashes: HashMap::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)
+            .map_err(|e| format!("Failed to open checkpoint '{}': {}", path.display(), e))?;
+        let checkpoint: Checkpoint = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("Invalid checkpoint '{}': {}", path.display(), e))?;
+        Ok(checkpoint)
+    }
+
+    /// Writes to a temporary file first so a crash mid-save leaves the old checkpoint intact.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            serde_json::to_writer(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Checks that the checkpoint was taken by a run over the same files with the same hash.
+    pub fn validate(&self, config: &ScanConfig, algorithm: HashAlgorithm) -> Result<(), String> {
+        if self.version != CHECKPOINT_VERSION {
+            return Err(format!("checkpoint version {} is not supported (expected {})", self.version, CHECKPOINT_VERSION));
+        }
+        if self.fingerprint != config.fingerprint() {
+            return Err(format!(
+                "checkpoint was taken with a different scan configuration: {:?}",
+                self.fingerprint
+            ));
+        }
+        if self.algorithm != algorithm {
+            return Err(format!("checkpoint was taken with {:?} hashes, not {:?}", self.algorithm, algorithm));
+        }
+        Ok(())
+    }
+
+    /// Forgets what was collected under directories that were only partly read, since the
+    /// walk will read them again, subdirectories included. A pending directory inside
+    /// another one is dropped as well: re-reading the outer one already reaches it.
+    fn discard_partial_directories(&mut self) {
+        let nested: Vec<PathBuf> = self
+            .pending_dirs
+            .keys()
+            .filter(|dir| dir.ancestors().skip(1).any(|parent| self.pending_dirs.contains_key(parent)))
+            .cloned()
+            .collect();
+        for dir in nested {
+            self.pending_dirs.remove(&dir);
+        }
+
+        let pending = &self.pending_dirs;
+        let in_pending = |path: &Path| path.ancestors().skip(1).any(|parent| pending.contains_key(parent));
+        self.files.retain(|f| !in_pending(&f.path));
+        self.errors.retain(|e| !in_pending(&e.path));
+    }
+}
+
+/// Runs the walk and duplicate detection while periodically saving a [`Checkpoint`],
+/// and can pick an interrupted run back up from it.
+///
+/// The checkpoint is removed once detection completes. A cancelled run saves it one
+/// last time so it can be resumed later.
+pub struct ResumableScan {
+    config: Arc<ScanConfig>,
+    algorithm: HashAlgorithm,
+    checkpoint_path: PathBuf,
+    interval: Duration,
+    events: Arc<EventBus>,
+    cancel: CancellationToken,
+}
+
+impl ResumableScan {
+    pub fn new(config: ScanConfig, algorithm: HashAlgorithm, checkpoint_path: PathBuf) -> Self {
+        ResumableScan {
+            config: Arc::new(config),
+            algorithm,
+            checkpoint_path,
+            interval: DEFAULT_INTERVAL,
+            events: Arc::new(EventBus::new()),
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    /// How often to save; the default is once a minute.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Starts a fresh run, or continues the one saved at the checkpoint path when `resume` is set.
+    pub fn run(&self, resume: bool) -> Result<DuplicateReport, Box<dyn std::error::Error>> {
+        self.run_collecting(resume).map(|(report, _)| report)
+    }
+
+    /// Like [`ResumableScan::run`], also returning every file the walk found, for reports
+    /// that look beyond the duplicate groups (e.g. [`crate::directories`]).
+    pub fn run_collecting(&self, resume: bool) -> Result<(DuplicateReport, Vec<FileMetadata>), Box<dyn std::error::Error>> {
+        let checkpoint = if resume {
+            let mut checkpoint = Checkpoint::load(&self.checkpoint_path)?;
+            checkpoint.validate(&self.config, self.algorithm)
+                .map_err(|e| format!("Cannot resume from '{}': {}", self.checkpoint_path.display(), e))?;
+            checkpoint.discard_partial_directories();
+            checkpoint
+        } else {
+            Checkpoint::new(&self.config, self.algorithm)
+        };
+
+        let recorder = Arc::new(Recorder {
+            state: Mutex::new(checkpoint),
+            path: self.checkpoint_path.clone(),
+            interval: self.interval,
+            last_save: Mutex::new(Instant::now()),
+            writing: Mutex::new(()),
+        });
+
+        if recorder.stage() == PipelineStage::Walking {
+            self.walk(&recorder)?;
+            if self.cancel.is_cancelled() {
+                recorder.save()?;
+                let state = recorder.state.lock().unwrap();
+                let report = DuplicateReport {
+                    groups: Vec::new(),
+                    errors: state.errors.clone(),
+                    incomplete_stages: vec![ScanStage::Walk, ScanStage::Hashing],
+                };
+                return Ok((report, state.files.clone()));
+            }
+            recorder.state.lock().unwrap().stage = PipelineStage::Hashing;
+            recorder.save()?;
+        }
+
+        let (files, walk_errors) = {
+            let state = recorder.state.lock().unwrap();
+            (state.files.clone(), state.errors.clone())
+        };
+
+        let store: Arc<dyn HashStore> = recorder.clone();
+        let mut finder = DuplicateFinder::new(self.algorithm)
+            .with_cancellation(self.cancel.clone())
+            .with_hash_store(store)
+            .with_events(Arc::clone(&self.events))
+            .with_read_options(self.config.read.clone())
+            .with_sampling(self.config.sampling.clone())
+            .with_verification(self.config.verify_bytes)
+            .with_reference_set(Arc::new(ReferenceSet::new(self.config.reference_paths.clone())));
+        if let Some(scheduler) = IoScheduler::from_config(&self.config) {
+            finder = finder.with_io_scheduler(Arc::new(scheduler));
+        }
+        let mut report = finder.find_duplicates(files.clone());
+
+        let mut errors = walk_errors;
+        errors.append(&mut report.errors);
+        report.errors = errors;
+
+        if report.is_complete() {
+            match fs::remove_file(&self.checkpoint_path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        } else {
+            recorder.save()?;
+        }
+
+        Ok((report, files))
+    }
+
+    fn walk(&self, recorder: &Recorder) -> Result<(), Box<dyn std::error::Error>> {
+        let walker = ParallelWalker::new(Arc::clone(&self.config), Arc::clone(&self.events), self.cancel.clone())?;
+        let starts: Vec<(PathBuf, usize)> = recorder.state.lock().unwrap()
+            .pending_dirs.iter()
+            .map(|(dir, depth)| (dir.clone(), *depth))
+            .collect();
+
+        let walked = walker.walk_from(&starts, |item| {
+            {
+                let mut state = recorder.state.lock().unwrap();
+                match item {
+                    Ok(file) => state.files.push(file),
+                    Err(e) => state.errors.push(e),
+                }
+            }
+            recorder.maybe_save();
+            true
+        }, Some(recorder));
+
+        // Resumed walks only count what they read themselves; report the whole run.
+        let mut stats = ScanStats::new();
+        {
+            let state = recorder.state.lock().unwrap();
+            stats.files_scanned = walked.files_scanned.max(state.files.len() as u64);
+            stats.total_size = state.files.iter().map(|f| f.size).sum();
+            stats.errors = state.errors.len() as u64;
+        }
+        self.events.publish(ProgressUpdate::Finished(stats));
+        Ok(())
+    }
+}
+
+/// Shared checkpoint state, fed by the walker and the hashing stage.
+struct Recorder {
+    state: Mutex<Checkpoint>,
+    path: PathBuf,
+    interval: Duration,
+    last_save: Mutex<Instant>,
+    /// Held while a snapshot is written, so saves land in the order they were taken.
+    writing: Mutex<()>,
+}
+
+impl Recorder {
+    fn stage(&self) -> PipelineStage {
+        self.state.lock().unwrap().stage
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let writing = self.writing.lock().unwrap();
+        self.write_snapshot(writing)?;
+        *self.last_save.lock().unwrap() = Instant::now();
+        Ok(())
+    }
+
+    /// Copies the state and writes the copy, so hashing threads are not held up by the
+    /// serialization and fsync.
+    fn write_snapshot(&self, _writing: MutexGuard<'_, ()>) -> io::Result<()> {
+        let snapshot = self.state.lock().unwrap().clone();
+        snapshot.save(&self.path)
+    }
+
+    /// Saves if the interval has passed. Failures are retried at the next opportunity;
+    /// losing one checkpoint should not abort the scan itself.
+    fn maybe_save(&self) {
+        {
+            let mut last_save = self.last_save.lock().unwrap();
+            if last_save.elapsed() < self.interval {
+                return;
+            }
+            *last_save = Instant::now();
+        }
+        // Сохранение уже идёт — следующее будет через интервал
+        if let Ok(writing) = self.writing.try_lock() {
+            let _ = self.write_snapshot(writing);
+        }
+    }
+}
+
+impl WalkObserver for Recorder {
+    fn directory_queued(&self, dir: &Path, depth: usize) {
+        self.state.lock().unwrap().pending_dirs.insert(dir.to_path_buf(), depth);
+    }
+
+    fn directory_done(&self, dir: &Path) {
+        self.state.lock().unwrap().pending_dirs.remove(dir);
+        self.maybe_save();
+    }
+}
+
+impl HashStore for Recorder {
+    fn lookup(&self, file: &FileMetadata, algorithm: HashAlgorithm, partial: bool) -> Option<FileHash> {
+        let state = self.state.lock().unwrap();
+        let stored = state.hashes.get(&file.path)?;
+        if stored.size != file.size || stored.modified != file.modified {
+            return None;
+        }
+        let hash = if partial { stored.prefix.as_ref() } else { stored.full.as_ref() }?;
+        (hash.algorithm == algorithm).then(|| hash.clone())
+    }
+
+    fn record(&self, file: &FileMetadata, hash: &FileHash) {
+        {
+            let mut state = self.state.lock().unwrap();
+            let stored = state.hashes.entry(file.path.clone()).or_default();
+            if stored.size != file.size || stored.modified != file.modified {
+                *stored = StoredHashes { size: file.size, modified: file.modified, ..Default::default() };
+            }
+            if hash.partial {
+                stored.prefix = Some(hash.clone());
+            } else {
+                stored.full = Some(hash.clone());
+            }
+        }
+        self.maybe_save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use tempfile::tempdir;
+
+    fn config_for(root: &Path) -> ScanConfig {
+        ScanConfig::build(Config {
+            root_paths: vec![root.to_path_buf()],
+            min_file_size: None,
+            max_file_size: None,
+            follow_symlinks: false,
+            exclude_patterns: vec![],
+            max_depth: None,
+            skip_hidden: true,
+            threads: Some(2),
+            sort_output: false,
+            io_policies: vec![],
+            read: Default::default(),
+            sampling: None,
+            verify_bytes: false,
+            reference_paths: vec![],
+            collapse_directories: false,
+            algorithm: HashAlgorithm::SHA256,
+            plugins: vec![],
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_resume_continues_from_pending_directories() {
+        let data = tempdir().unwrap();
+        let state = tempdir().unwrap();
+        let checkpoint_path = state.path().join("scan.checkpoint");
+
+        fs::create_dir_all(data.path().join("done")).unwrap();
+        fs::create_dir_all(data.path().join("todo/deeper")).unwrap();
+        fs::write(data.path().join("done/a.txt"), "same").unwrap();
+        fs::write(data.path().join("todo/b.txt"), "same").unwrap();
+        fs::write(data.path().join("todo/deeper/c.txt"), "same").unwrap();
+
+        // Simulate a run that died after reading the root and `done/`, half-way through `todo/`.
+        let config = config_for(data.path());
+        let mut checkpoint = Checkpoint::new(&config, HashAlgorithm::SHA256);
+        checkpoint.pending_dirs = BTreeMap::from([(data.path().join("todo"), 1)]);
+        for name in ["done/a.txt", "todo/b.txt"] {
+            let path = data.path().join(name);
+            let metadata = fs::metadata(&path).unwrap();
+            checkpoint.files.push(FileMetadata { path, size: metadata.len(), hash: None, modified: metadata.modified().ok(), created: None });
+        }
+        checkpoint.save(&checkpoint_path).unwrap();
+
+        let report = ResumableScan::new(config, HashAlgorithm::SHA256, checkpoint_path.clone())
+            .run(true)
+            .unwrap();
+
+        assert!(report.is_complete());
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].files.len(), 3);
+        assert!(!checkpoint_path.exists());
+    }
+
+    #[test]
+    fn test_resume_does_not_record_files_of_nested_pending_directories_twice() {
+        let data = tempdir().unwrap();
+        let state = tempdir().unwrap();
+        let checkpoint_path = state.path().join("scan.checkpoint");
+
+        fs::create_dir_all(data.path().join("todo/sub")).unwrap();
+        fs::write(data.path().join("todo/sub/x"), "same").unwrap();
+        fs::write(data.path().join("y"), "same").unwrap();
+
+        // `todo/` был прочитан наполовину, `todo/sub/` уже поставлен в очередь и частично обработан
+        let config = config_for(data.path());
+        let mut checkpoint = Checkpoint::new(&config, HashAlgorithm::SHA256);
+        checkpoint.pending_dirs = BTreeMap::from([(data.path().join("todo"), 1), (data.path().join("todo/sub"), 2)]);
+        for name in ["y", "todo/sub/x"] {
+            let path = data.path().join(name);
+            let metadata = fs::metadata(&path).unwrap();
+            checkpoint.files.push(FileMetadata { path, size: metadata.len(), hash: None, modified: metadata.modified().ok(), created: None });
+        }
+        checkpoint.save(&checkpoint_path).unwrap();
+
+        let (report, files) = ResumableScan::new(config, HashAlgorithm::SHA256, checkpoint_path)
+            .run_collecting(true)
+            .unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].files.len(), 2);
+    }
+
+    #[test]
+    fn test_resume_rejects_different_config() {
+        let data = tempdir().unwrap();
+        let state = tempdir().unwrap();
+        let checkpoint_path = state.path().join("scan.checkpoint");
+
+        Checkpoint::new(&config_for(data.path()), HashAlgorithm::SHA256).save(&checkpoint_path).unwrap();
+
+        let other = config_for(&data.path().join("elsewhere"));
+        let result = ResumableScan::new(other, HashAlgorithm::SHA256, checkpoint_path.clone()).run(true);
+        assert!(result.is_err());
+
+        let result = ResumableScan::new(config_for(data.path()), HashAlgorithm::Blake3, checkpoint_path).run(true);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_cancelled_run_leaves_checkpoint() {
+        let data = tempdir().unwrap();
+        let state = tempdir().unwrap();
+        let checkpoint_path = state.path().join("scan.checkpoint");
+        fs::write(data.path().join("a.txt"), "same").unwrap();
+        fs::write(data.path().join("b.txt"), "same").unwrap();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let report = ResumableScan::new(config_for(data.path()), HashAlgorithm::SHA256, checkpoint_path.clone())
+            .with_cancellation(cancel)
+            .run(false)
+            .unwrap();
+        assert!(!report.is_complete());
+
+        let checkpoint = Checkpoint::load(&checkpoint_path).unwrap();
+        assert_eq!(checkpoint.stage, PipelineStage::Walking);
+        assert!(checkpoint.pending_dirs.contains_key(data.path()));
+
+        let report = ResumableScan::new(config_for(data.path()), HashAlgorithm::SHA256, checkpoint_path)
+            .run(true)
+            .unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.groups.len(), 1);
+    }
+}
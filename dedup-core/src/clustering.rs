@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use crate::similarity::SimilarPair;
+
+/// How pairs above the threshold are turned into groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterMethod {
+    /// Connected components: a chain of similar files forms one group, even when its ends differ.
+    #[default]
+    Connected,
+    /// Every two files in a group are at least `threshold` similar.
+    CompleteLinkage,
+    /// Each group is one central file plus the files at least `threshold` similar to it.
+    Medoid,
+}
+
+impl FromStr for ClusterMethod {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "connected" => Ok(ClusterMethod::Connected),
+            "complete" | "complete_linkage" => Ok(ClusterMethod::CompleteLinkage),
+            "medoid" => Ok(ClusterMethod::Medoid),
+            other => Err(format!("Unknown cluster method '{}', expected connected, complete or medoid", other)),
+        }
+    }
+}
+
+impl fmt::Display for ClusterMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ClusterMethod::Connected => "connected",
+            ClusterMethod::CompleteLinkage => "complete",
+            ClusterMethod::Medoid => "medoid",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Near-duplicate files grouped together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cluster {
+    /// Members, sorted by path.
+    pub files: Vec<PathBuf>,
+    /// The member most similar to all others; the one to keep.
+    pub representative: PathBuf,
+    /// Lowest similarity between two members; unrelated members count as 0.
+    pub min_similarity: f64,
+    pub avg_similarity: f64,
+}
+
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        UnionFind { parent: (0..size).collect() }
+    }
+
+    fn find(&mut self, node: usize) -> usize {
+        let mut root = node;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut node = node;
+        while self.parent[node] != root {
+            let next = self.parent[node];
+            self.parent[node] = root;
+            node = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+}
+
+/// Pairwise similarities between the files named in a set of pairs.
+struct SimilarityGraph {
+    files: Vec<PathBuf>,
+    scores: HashMap<(usize, usize), f64>,
+}
+
+impl SimilarityGraph {
+    fn new(pairs: &[SimilarPair]) -> Self {
+        let files: Vec<PathBuf> = pairs
+            .iter()
+            .flat_map(|p| [p.left.clone(), p.right.clone()])
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let index: HashMap<&PathBuf, usize> = files.iter().enumerate().map(|(i, f)| (f, i)).collect();
+        let mut scores = HashMap::new();
+        for pair in pairs {
+            let (a, b) = (index[&pair.left], index[&pair.right]);
+            // Несколько методов могут оценить одну пару; берём наибольшую оценку
+            let score = scores.entry((a.min(b), a.max(b))).or_insert(0.0f64);
+            *score = score.max(pair.similarity);
+        }
+        SimilarityGraph { files, scores }
+    }
+
+    fn score(&self, a: usize, b: usize) -> f64 {
+        if a == b {
+            return 1.0;
+        }
+        self.scores.get(&(a.min(b), a.max(b))).copied().unwrap_or(0.0)
+    }
+
+    fn components(&self, threshold: f64) -> Vec<Vec<usize>> {
+        let mut sets = UnionFind::new(self.files.len());
+        for (&(a, b), &score) in &self.scores {
+            if score >= threshold {
+                sets.union(a, b);
+            }
+        }
+        let mut components: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for node in 0..self.files.len() {
+            components.entry(sets.find(node)).or_default().push(node);
+        }
+        components.into_values().collect()
+    }
+
+    fn complete_linkage(&self, component: Vec<usize>, threshold: f64) -> Vec<Vec<usize>> {
+        let mut clusters: Vec<Vec<usize>> = component.into_iter().map(|node| vec![node]).collect();
+        loop {
+            let mut best: Option<(f64, usize, usize)> = None;
+            for i in 0..clusters.len() {
+                for j in i + 1..clusters.len() {
+                    let linkage = clusters[i]
+                        .iter()
+                        .flat_map(|&a| clusters[j].iter().map(move |&b| (a, b)))
+                        .map(|(a, b)| self.score(a, b))
+                        .fold(f64::INFINITY, f64::min);
+                    if linkage >= threshold && best.is_none_or(|(score, _, _)| linkage > score) {
+                        best = Some((linkage, i, j));
+                    }
+                }
+            }
+            let Some((_, i, j)) = best else { break };
+            let merged = clusters.swap_remove(j);
+            clusters[i].extend(merged);
+        }
+        clusters
+    }
+
+    fn medoids(&self, component: Vec<usize>, threshold: f64) -> Vec<Vec<usize>> {
+        let weight = |node: usize| -> f64 {
+            component.iter().filter(|&&other| other != node).map(|&other| self.score(node, other)).filter(|&s| s >= threshold).sum()
+        };
+        let mut order: Vec<(f64, usize)> = component.iter().map(|&node| (weight(node), node)).collect();
+        order.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        let mut assigned = BTreeSet::new();
+        let mut clusters = Vec::new();
+        for (_, medoid) in order {
+            if !assigned.insert(medoid) {
+                continue;
+            }
+            let mut members = vec![medoid];
+            for &other in &component {
+                if !assigned.contains(&other) && self.score(medoid, other) >= threshold {
+                    assigned.insert(other);
+                    members.push(other);
+                }
+            }
+            clusters.push(members);
+        }
+        clusters
+    }
+
+    fn describe(&self, mut members: Vec<usize>, medoid: Option<usize>) -> Cluster {
+        members.sort_unstable();
+        let mut scores = Vec::new();
+        for (i, &a) in members.iter().enumerate() {
+            scores.extend(members[i + 1..].iter().map(|&b| self.score(a, b)));
+        }
+        let representative = medoid.unwrap_or_else(|| {
+            let total = |node: usize| members.iter().map(|&other| self.score(node, other)).sum::<f64>();
+            members.iter().copied().max_by(|&a, &b| total(a).total_cmp(&total(b)).then(b.cmp(&a))).unwrap()
+        });
+        Cluster {
+            files: members.iter().map(|&node| self.files[node].clone()).collect(),
+            representative: self.files[representative].clone(),
+            min_similarity: scores.iter().copied().fold(1.0, f64::min),
+            avg_similarity: scores.iter().sum::<f64>() / scores.len() as f64,
+        }
+    }
+}
+
+/// Groups the files in `pairs` by similarity of at least `threshold`.
+///
+/// Pairs may come from any matcher; a pair scored by several methods uses its highest
+/// score. Files left alone are not returned. Largest groups come first.
+pub fn cluster_pairs(pairs: &[SimilarPair], threshold: f64, method: ClusterMethod) -> Vec<Cluster> {
+    let graph = SimilarityGraph::new(pairs);
+    let mut clusters = Vec::new();
+    for component in graph.components(threshold) {
+        if component.len() < 2 {
+            continue;
+        }
+        match method {
+            ClusterMethod::Connected => clusters.push(graph.describe(component, None)),
+            ClusterMethod::CompleteLinkage => clusters.extend(
+                graph.complete_linkage(component, threshold).into_iter().filter(|c| c.len() > 1).map(|c| graph.describe(c, None)),
+            ),
+            ClusterMethod::Medoid => clusters.extend(
+                graph.medoids(component, threshold).into_iter().filter(|c| c.len() > 1).map(|c| {
+                    let medoid = c[0];
+                    graph.describe(c, Some(medoid))
+                }),
+            ),
+        }
+    }
+    clusters.sort_by(|a, b| b.files.len().cmp(&a.files.len()).then_with(|| a.files.cmp(&b.files)));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::similarity::BLOCK_METHOD;
+
+    fn pair(left: &str, right: &str, similarity: f64) -> SimilarPair {
+        SimilarPair {
+            left: PathBuf::from(left),
+            right: PathBuf::from(right),
+            left_size: 0,
+            right_size: 0,
+            similarity,
+            positional: similarity,
+            method: BLOCK_METHOD.to_string(),
+            ranges: Vec::new(),
+        }
+    }
+
+    // a-b-c is a chain: a and c are only related through b; d-e is a separate pair
+    fn chain() -> Vec<SimilarPair> {
+        vec![pair("a", "b", 0.9), pair("b", "c", 0.8), pair("a", "c", 0.3), pair("d", "e", 0.95)]
+    }
+
+    fn names(cluster: &Cluster) -> Vec<&str> {
+        cluster.files.iter().map(|f| f.to_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_connected_components_follow_chains() {
+        let clusters = cluster_pairs(&chain(), 0.7, ClusterMethod::Connected);
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(names(&clusters[0]), ["a", "b", "c"]);
+        assert_eq!(clusters[0].representative, PathBuf::from("b"));
+        assert_eq!(clusters[0].min_similarity, 0.3);
+        assert!((clusters[0].avg_similarity - 2.0 / 3.0).abs() < 1e-9);
+        assert_eq!(names(&clusters[1]), ["d", "e"]);
+    }
+
+    #[test]
+    fn test_complete_linkage_and_medoid_split_chains() {
+        let complete = cluster_pairs(&chain(), 0.7, ClusterMethod::CompleteLinkage);
+        let groups: Vec<Vec<&str>> = complete.iter().map(names).collect();
+        assert_eq!(groups, vec![vec!["a", "b"], vec!["d", "e"]]);
+        assert!(complete.iter().all(|c| c.min_similarity >= 0.7));
+
+        let medoid = cluster_pairs(&chain(), 0.7, ClusterMethod::Medoid);
+        assert_eq!(names(&medoid[0]), ["a", "b", "c"]);
+        assert_eq!(medoid[0].representative, PathBuf::from("b"));
+
+        assert!(cluster_pairs(&chain(), 0.99, ClusterMethod::Connected).is_empty());
+    }
+}
@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use rayon::prelude::*;
+use crate::cancel::CancellationToken;
+use crate::config::ScanConfig;
+use crate::error::{ScanError, ScanStage};
+use crate::events::EventBus;
+use crate::hasher;
+use crate::models::{FileHash, FileMetadata, HashAlgorithm, ProgressUpdate};
+use crate::progress::{ProgressTracker, TrackedStage};
+use crate::walker::ParallelWalker;
+
+/// How the `right` tree differs from the `left` one, by content. Paths are relative to
+/// their root and sorted.
+#[derive(Debug, Clone, Default)]
+pub struct TreeComparison {
+    /// In `left` only, with no copy anywhere in `right`.
+    pub missing: Vec<PathBuf>,
+    /// Same content under a different path, each file in `right` claimed at most once. The
+    /// new path may also be listed in `changed` when the file was moved over one that
+    /// existed in `left`. A left file whose content `right` only holds at a path that is
+    /// unchanged or already claimed is neither moved nor missing.
+    pub moved: Vec<MovedFile>,
+    /// Same path, different content.
+    pub changed: Vec<PathBuf>,
+    /// In `right` only, with no counterpart in `left`.
+    pub added: Vec<PathBuf>,
+    pub unchanged: usize,
+    pub errors: Vec<ScanError>,
+    /// Non-empty when the comparison was cancelled; the lists above are then partial.
+    pub incomplete_stages: Vec<ScanStage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MovedFile {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl TreeComparison {
+    pub fn is_complete(&self) -> bool {
+        self.incomplete_stages.is_empty()
+    }
+
+    /// True when nothing from `left` is missing, moved or changed in `right`.
+    pub fn is_in_sync(&self) -> bool {
+        self.is_complete() && self.errors.is_empty() && self.missing.is_empty() && self.moved.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares two roots by content, e.g. a source tree and its backup.
+///
+/// Both roots are walked with the filters of the given config (its own roots are ignored).
+/// Files at the same relative path are hashed only when their sizes match; files present
+/// on one side only are hashed only when the other side has an unmatched file of that size.
+pub struct TreeComparer {
+    config: ScanConfig,
+    algorithm: HashAlgorithm,
+    events: Arc<EventBus>,
+    cancel: CancellationToken,
+}
+
+type Tree = BTreeMap<PathBuf, FileMetadata>;
+
+impl TreeComparer {
+    pub fn new(config: ScanConfig, algorithm: HashAlgorithm) -> Self {
+        TreeComparer { config, algorithm, events: Arc::new(EventBus::new()), cancel: CancellationToken::new() }
+    }
+
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn events(&self) -> &EventBus {
+        &self.events
+    }
+
+    pub fn compare(&self, left: &Path, right: &Path) -> Result<TreeComparison, Box<dyn std::error::Error>> {
+        let mut result = TreeComparison::default();
+        let left_tree = self.walk(left, &mut result.errors)?;
+        let right_tree = self.walk(right, &mut result.errors)?;
+        if self.cancel.is_cancelled() {
+            result.incomplete_stages = vec![ScanStage::Walk, ScanStage::Hashing];
+            return Ok(result);
+        }
+
+        // Same path on both sides: equal sizes still need a hash, different sizes do not.
+        let mut to_hash = Vec::new();
+        for (path, file) in &left_tree {
+            match right_tree.get(path) {
+                Some(other) if other.size == file.size => to_hash.extend([file.clone(), other.clone()]),
+                Some(_) => result.changed.push(path.clone()),
+                None => {}
+            }
+        }
+
+        // One side only: hash when the other side has an unmatched file of the same size.
+        // A file renamed over an existing path lands on a changed path in `right`, so every
+        // right file at a shared path is a possible move target as well.
+        let left_only: Vec<&FileMetadata> = left_tree.iter().filter(|(p, _)| !right_tree.contains_key(*p)).map(|(_, f)| f).collect();
+        let right_only: Vec<&FileMetadata> = right_tree.iter().filter(|(p, _)| !left_tree.contains_key(*p)).map(|(_, f)| f).collect();
+        let left_sizes: HashSet<u64> = left_only.iter().map(|f| f.size).collect();
+        let right_sizes: HashSet<u64> = right_tree.values().map(|f| f.size).collect();
+        to_hash.extend(left_only.iter().filter(|f| right_sizes.contains(&f.size)).map(|f| (*f).clone()));
+        to_hash.extend(right_only.iter().filter(|f| left_sizes.contains(&f.size)).map(|f| (*f).clone()));
+        to_hash.extend(
+            left_tree
+                .iter()
+                .filter_map(|(path, file)| right_tree.get(path).filter(|other| other.size != file.size))
+                .filter(|other| left_sizes.contains(&other.size))
+                .cloned(),
+        );
+
+        let (hashes, hash_errors, complete) = self.hash_files(to_hash);
+        result.errors.extend(hash_errors);
+        if !complete {
+            result.incomplete_stages.push(ScanStage::Hashing);
+        }
+        let failed: HashSet<&Path> = result.errors.iter().map(|e| e.path.as_path()).collect();
+
+        for (path, file) in &left_tree {
+            let Some(other) = right_tree.get(path) else { continue };
+            if other.size != file.size {
+                continue;
+            }
+            match (hashes.get(&file.path), hashes.get(&other.path)) {
+                (Some(a), Some(b)) if a == b => result.unchanged += 1,
+                (Some(_), Some(_)) => result.changed.push(path.clone()),
+                _ => {}
+            }
+        }
+
+        // Pair up one-sided files by content, in path order; leftovers are missing or added.
+        // Files at changed paths take part as targets only, after the one-sided ones.
+        let changed: HashSet<&Path> = result.changed.iter().map(PathBuf::as_path).collect();
+        let changed_right = right_tree.iter().filter(|(p, _)| changed.contains(p.as_path())).map(|(_, f)| f);
+        let targets: Vec<&FileMetadata> = right_only.iter().copied().chain(changed_right).collect();
+        let mut unmatched_right: HashMap<(u64, &FileHash), Vec<&FileMetadata>> = HashMap::new();
+        for file in targets.iter().rev() {
+            if let Some(hash) = hashes.get(&file.path) {
+                unmatched_right.entry((file.size, hash)).or_default().push(file);
+            }
+        }
+        // Копия, уже занятая другим файлом или лежащая на неизменном пути, — не пропажа
+        let in_right: HashSet<(u64, &FileHash)> =
+            right_tree.values().filter_map(|f| Some((f.size, hashes.get(&f.path)?))).collect();
+        let mut moved_to = HashSet::new();
+        for file in &left_only {
+            let target = hashes.get(&file.path).and_then(|hash| unmatched_right.get_mut(&(file.size, hash))?.pop());
+            match target {
+                Some(target) => {
+                    moved_to.insert(target.path.as_path());
+                    result.moved.push(MovedFile { from: relative(left, &file.path), to: relative(right, &target.path) });
+                }
+                None if failed.contains(file.path.as_path()) => {}
+                None if hashes.get(&file.path).is_some_and(|hash| in_right.contains(&(file.size, hash))) => {}
+                None => result.missing.push(relative(left, &file.path)),
+            }
+        }
+        result.added = right_only
+            .iter()
+            .filter(|f| !moved_to.contains(f.path.as_path()) && !failed.contains(f.path.as_path()))
+            .map(|f| relative(right, &f.path))
+            .collect();
+
+        result.changed.sort();
+        Ok(result)
+    }
+
+    /// Files under `root`, keyed by their path relative to it.
+    fn walk(&self, root: &Path, errors: &mut Vec<ScanError>) -> Result<Tree, Box<dyn std::error::Error>> {
+        let config = ScanConfig { root_paths: vec![root.to_path_buf()], reference_paths: Vec::new(), ..self.config.clone() };
+        let walker = ParallelWalker::new(Arc::new(config), Arc::clone(&self.events), self.cancel.clone())?;
+        let items = Mutex::new(Vec::new());
+        let stats = walker.walk(|item| {
+            items.lock().unwrap().push(item);
+            true
+        });
+        self.events.publish(ProgressUpdate::Finished(stats));
+
+        let mut tree = Tree::new();
+        for item in items.into_inner().unwrap() {
+            match item {
+                Ok(file) => {
+                    tree.insert(relative(root, &file.path), file);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+        Ok(tree)
+    }
+
+    /// Full hashes by absolute path; the flag is false if cancellation skipped any file.
+    fn hash_files(&self, files: Vec<FileMetadata>) -> (HashMap<PathBuf, FileHash>, Vec<ScanError>, bool) {
+        let progress = ProgressTracker::new(&self.events, TrackedStage::Hashing { partial: false });
+        progress.add_work(files.len() as u64, files.iter().map(|f| f.size).sum());
+
+        let results: Vec<Option<Result<(PathBuf, FileHash), ScanError>>> = files
+            .into_par_iter()
+            .map(|file| {
+                if self.cancel.is_cancelled() {
+                    return None;
+                }
+                let hashed = hasher::hash_file_observed(&file.path, self.algorithm, None, &self.config.read, &self.cancel, &|bytes| {
+                    progress.advance(bytes, &file.path)
+                });
+                if hashed.is_err() && self.cancel.is_cancelled() {
+                    return None;
+                }
+                progress.file_done(&file.path);
+                Some(match hashed {
+                    Ok(hash) => Ok((file.path, hash)),
+                    Err(e) => Err(ScanError::from_io(&file.path, ScanStage::Hashing, &e)),
+                })
+            })
+            .collect();
+        progress.finish();
+
+        let mut hashes = HashMap::new();
+        let mut errors = Vec::new();
+        let mut complete = true;
+        for result in results {
+            match result {
+                Some(Ok((path, hash))) => {
+                    hashes.insert(path, hash);
+                }
+                Some(Err(e)) => errors.push(e),
+                None => complete = false,
+            }
+        }
+        (hashes, errors, complete)
+    }
+}
+
+fn relative(root: &Path, path: &Path) -> PathBuf {
+    path.strip_prefix(root).unwrap_or(path).to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn base_config() -> ScanConfig {
+        ScanConfig::build(Config {
+            root_paths: vec![],
+            min_file_size: None,
+            max_file_size: None,
+            follow_symlinks: false,
+            exclude_patterns: vec![],
+            max_depth: None,
+            skip_hidden: false,
+            threads: Some(2),
+            sort_output: false,
+            io_policies: vec![],
+            read: Default::default(),
+            sampling: None,
+            verify_bytes: false,
+            reference_paths: vec![],
+            collapse_directories: false,
+            algorithm: HashAlgorithm::SHA256,
+            plugins: vec![],
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_compare_classifies_differences() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let backup = dir.path().join("backup");
+        fs::create_dir_all(source.join("docs")).unwrap();
+        fs::create_dir_all(backup.join("archive")).unwrap();
+
+        fs::write(source.join("same.txt"), "unchanged").unwrap();
+        fs::write(backup.join("same.txt"), "unchanged").unwrap();
+        fs::write(source.join("edited.txt"), "version one").unwrap();
+        fs::write(backup.join("edited.txt"), "version two").unwrap();
+        fs::write(source.join("docs/report.pdf"), "report body").unwrap();
+        fs::write(backup.join("archive/report-2024.pdf"), "report body").unwrap();
+        fs::write(source.join("lost.txt"), "only in source").unwrap();
+        fs::write(backup.join("extra.txt"), "only in backup").unwrap();
+
+        let comparison = TreeComparer::new(base_config(), HashAlgorithm::Blake3).compare(&source, &backup).unwrap();
+
+        assert!(comparison.is_complete());
+        assert!(!comparison.is_in_sync());
+        assert_eq!(comparison.unchanged, 1);
+        assert_eq!(comparison.changed, vec![PathBuf::from("edited.txt")]);
+        assert_eq!(comparison.moved, vec![MovedFile { from: "docs/report.pdf".into(), to: "archive/report-2024.pdf".into() }]);
+        assert_eq!(comparison.missing, vec![PathBuf::from("lost.txt")]);
+        assert_eq!(comparison.added, vec![PathBuf::from("extra.txt")]);
+    }
+
+    #[test]
+    fn test_content_kept_elsewhere_in_right_is_not_missing() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let backup = dir.path().join("backup");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&backup).unwrap();
+
+        fs::write(source.join("a"), "X").unwrap();
+        fs::write(source.join("same"), "X").unwrap();
+        fs::write(backup.join("same"), "X").unwrap();
+
+        let comparison = TreeComparer::new(base_config(), HashAlgorithm::Blake3).compare(&source, &backup).unwrap();
+
+        assert!(comparison.missing.is_empty(), "{:?}", comparison.missing);
+        assert!(comparison.moved.is_empty());
+        assert_eq!(comparison.unchanged, 1);
+    }
+
+    #[test]
+    fn test_file_moved_over_an_existing_path_is_not_missing() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        let backup = dir.path().join("backup");
+        fs::create_dir_all(&source).unwrap();
+        fs::create_dir_all(&backup).unwrap();
+
+        fs::write(source.join("draft.txt"), "final text").unwrap();
+        fs::write(source.join("report.txt"), "old report").unwrap();
+        fs::write(backup.join("report.txt"), "final text").unwrap();
+        fs::write(source.join("notes.txt"), "short").unwrap();
+        fs::write(backup.join("notes.txt"), "a much longer note").unwrap();
+        fs::write(source.join("longer.txt"), "a much longer note").unwrap();
+
+        let comparison = TreeComparer::new(base_config(), HashAlgorithm::Blake3).compare(&source, &backup).unwrap();
+
+        assert!(comparison.missing.is_empty(), "{:?}", comparison.missing);
+        assert_eq!(comparison.changed, vec![PathBuf::from("notes.txt"), PathBuf::from("report.txt")]);
+        assert_eq!(
+            comparison.moved,
+            vec![
+                MovedFile { from: "draft.txt".into(), to: "report.txt".into() },
+                MovedFile { from: "longer.txt".into(), to: "notes.txt".into() },
+            ]
+        );
+        assert!(comparison.added.is_empty());
+    }
+}
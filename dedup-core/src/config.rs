@@ -1,11 +1,16 @@
-use std::{env, fs};
+use std::fs;
 use std::path::{Path, PathBuf};
 use glob::Pattern;
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::hasher::SamplingOptions;
+use crate::models::HashAlgorithm;
+use crate::plugins::{default_plugins, PluginConfig};
+use crate::read_backend::ReadOptions;
 
 
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
 pub struct Config {
     pub root_paths: Vec<PathBuf>,
     pub min_file_size: Option<u64>,
@@ -14,9 +19,63 @@ pub struct Config {
     pub exclude_patterns: Vec<String>,
     pub max_depth: Option<usize>,
     pub skip_hidden: bool,
+    #[serde(default)]
+    pub threads: Option<usize>,
+    #[serde(default)]
+    pub sort_output: bool,
+    #[serde(default)]
+    pub io_policies: Vec<IoPolicy>,
+    #[serde(default)]
+    pub read: ReadOptions,
+    /// `null` turns the sampling pass off.
+    #[serde(default = "default_sampling")]
+    pub sampling: Option<SamplingOptions>,
+    #[serde(default)]
+    pub verify_bytes: bool,
+    #[serde(default)]
+    pub reference_paths: Vec<PathBuf>,
+    #[serde(default)]
+    pub collapse_directories: bool,
+    #[serde(default = "default_algorithm")]
+    pub algorithm: HashAlgorithm,
+    /// Stages of the plugin pipeline, in the order they run; see [`crate::plugins::PluginPipeline`].
+    #[serde(default = "default_plugins")]
+    pub plugins: Vec<PluginConfig>,
 }
 
-#[derive(Debug)]
+fn default_sampling() -> Option<SamplingOptions> {
+    Some(SamplingOptions::default())
+}
+
+fn default_algorithm() -> HashAlgorithm {
+    HashAlgorithm::SHA256
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            root_paths: Vec::new(),
+            min_file_size: None,
+            max_file_size: None,
+            follow_symlinks: false,
+            exclude_patterns: Vec::new(),
+            max_depth: None,
+            skip_hidden: false,
+            threads: None,
+            sort_output: false,
+            io_policies: Vec::new(),
+            read: ReadOptions::default(),
+            sampling: default_sampling(),
+            verify_bytes: false,
+            reference_paths: Vec::new(),
+            collapse_directories: false,
+            algorithm: default_algorithm(),
+            plugins: default_plugins(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ScanConfig {
     pub root_paths: Vec<PathBuf>,
     pub min_file_size: Option<u64>,
@@ -25,9 +84,86 @@ pub struct ScanConfig {
     pub exclude_patterns: Vec<Pattern>,
     pub max_depth: Option<usize>,
     pub skip_hidden: bool,
+    /// Worker threads for the parallel walker; `None` uses one per CPU.
+    pub threads: Option<usize>,
+    /// Sort collected results by path so reports are reproducible.
+    pub sort_output: bool,
+    /// Read scheduling for files under specific roots; see [`crate::io_scheduler::IoScheduler`].
+    pub io_policies: Vec<IoPolicy>,
+    /// Read backend and buffer settings for hashing.
+    pub read: ReadOptions,
+    /// Sampled-block pass for large files before the full hash; `None` skips it.
+    pub sampling: Option<SamplingOptions>,
+    /// Confirm every group byte for byte before reporting it.
+    pub verify_bytes: bool,
+    /// Protected roots, walked alongside `root_paths`. Their files are only ever reported as
+    /// the existing copy of a target file; see [`crate::reference::ReferenceSet`].
+    pub reference_paths: Vec<PathBuf>,
+    /// List identical directories once and leave out the file groups inside them.
+    pub collapse_directories: bool,
+}
+
+/// How reads are scheduled for files under `root`, e.g. a NAS on spinning disks.
+/// The most specific matching root wins; files outside every policy are read as before.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct IoPolicy {
+    pub root: PathBuf,
+    #[serde(default)]
+    pub read_order: ReadOrder,
+    /// Concurrent readers per device; `None` uses one per worker thread.
+    #[serde(default)]
+    pub readers_per_device: Option<usize>,
+    /// Combined read rate for everything under `root`, in megabytes per second.
+    #[serde(default)]
+    pub max_mb_per_sec: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadOrder {
+    /// No ordering; best for SSDs.
+    #[default]
+    Any,
+    /// By inode number, which roughly follows allocation order on most filesystems.
+    Inode,
+    /// By the physical offset of the first extent (FIEMAP, Linux only); inode order elsewhere.
+    Extent,
+}
+
+/// The settings that decide which files a scan sees. Two configs with equal fingerprints
+/// walk the same set of files, so a checkpoint from one can be resumed with the other.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanFingerprint {
+    pub root_paths: Vec<PathBuf>,
+    pub min_file_size: Option<u64>,
+    pub max_file_size: Option<u64>,
+    pub follow_symlinks: bool,
+    pub exclude_patterns: Vec<String>,
+    pub max_depth: Option<usize>,
+    pub skip_hidden: bool,
+    #[serde(default)]
+    pub reference_paths: Vec<PathBuf>,
 }
 
 impl ScanConfig {
+    /// Target roots followed by reference roots, in the order they are walked.
+    pub fn walk_roots(&self) -> impl Iterator<Item = &PathBuf> {
+        self.root_paths.iter().chain(&self.reference_paths)
+    }
+
+    pub fn fingerprint(&self) -> ScanFingerprint {
+        ScanFingerprint {
+            root_paths: self.root_paths.clone(),
+            min_file_size: self.min_file_size,
+            max_file_size: self.max_file_size,
+            follow_symlinks: self.follow_symlinks,
+            exclude_patterns: self.exclude_patterns.iter().map(|p| p.as_str().to_string()).collect(),
+            max_depth: self.max_depth,
+            skip_hidden: self.skip_hidden,
+            reference_paths: self.reference_paths.clone(),
+        }
+    }
+
     pub fn build(config: Config) -> Result<Self, glob::PatternError> {
         let exclude_patterns: Result<Vec<Pattern>, glob::PatternError> = config.exclude_patterns
             .into_iter()
@@ -42,6 +178,14 @@ impl ScanConfig {
             exclude_patterns: exclude_patterns?,
             max_depth: config.max_depth,
             skip_hidden: config.skip_hidden,
+            threads: config.threads,
+            sort_output: config.sort_output,
+            io_policies: config.io_policies,
+            read: config.read,
+            sampling: config.sampling,
+            verify_bytes: config.verify_bytes,
+            reference_paths: config.reference_paths,
+            collapse_directories: config.collapse_directories,
         })
     }
 
@@ -70,4 +214,37 @@ pub fn read_config_file(config_path: &Path) -> Result<Config, Box<dyn std::error
         .map_err(|e| format!("Invalid JSON in config file '{}': {}", config_path.display(), e))?;
 
     Ok(config)
-}
\ No newline at end of file
+}
+
+/// JSON Schema for config files, for editor completion and linting.
+///
+/// Every key is optional, since a file is only one layer of the configuration (see
+/// [`crate::config_loader::ConfigLoader`]), and unknown keys are rejected. `profile` and
+/// `profiles` are added to the [`Config`] fields; a profile takes the same keys plus
+/// `inherits`.
+pub fn json_schema() -> serde_json::Value {
+    let mut schema = serde_json::to_value(schemars::schema_for!(Config)).expect("schema serializes");
+    let root = schema.as_object_mut().expect("schema is an object");
+    root.remove("required");
+    root.insert("title".into(), "dedup configuration".into());
+    root.insert("additionalProperties".into(), false.into());
+
+    let mut profile_properties = root["properties"].clone();
+    profile_properties["inherits"] = serde_json::json!({ "description": "Profile whose settings apply first.", "type": "string" });
+    root["$defs"]["Profile"] = serde_json::json!({
+        "description": "Named settings, selected with `profile`.",
+        "type": "object",
+        "properties": profile_properties,
+        "additionalProperties": false,
+    });
+    root["properties"]["profiles"] = serde_json::json!({
+        "description": "Named profiles; see `profile`.",
+        "type": "object",
+        "additionalProperties": { "$ref": "#/$defs/Profile" },
+    });
+    root["properties"]["profile"] = serde_json::json!({
+        "description": "Profile to apply over the settings in this file.",
+        "type": "string",
+    });
+    schema
+}
@@ -0,0 +1,532 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::{Map, Value};
+use crate::config::Config;
+use crate::validation::{self, InvalidConfig, Diagnostic, Severity};
+
+/// File names tried in each system and user config directory.
+const DIRECTORY_FILES: [&str; 2] = ["config.toml", "config.json"];
+/// File names tried in the working directory and each of its ancestors; `conf.json` is
+/// the name older setups used.
+const PROJECT_FILES: [&str; 5] = ["dedup.toml", "dedup.json", ".dedup.toml", ".dedup.json", "conf.json"];
+const ENV_PREFIX: &str = "DEDUP_";
+/// Names a config file to use instead of project file discovery.
+const ENV_CONFIG_FILE: &str = "DEDUP_CONFIG";
+/// Table holding the named profiles, and the key selecting one.
+const PROFILES_KEY: &str = "profiles";
+const PROFILE_KEY: &str = "profile";
+/// Names the profile a profile inherits from.
+const INHERITS_KEY: &str = "inherits";
+
+/// Where a configuration value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    System(PathBuf),
+    User(PathBuf),
+    Project(PathBuf),
+    /// Set by the named profile, or one it inherits from.
+    Profile(String),
+    Environment(String),
+    CommandLine,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::System(path) => write!(f, "system file {}", path.display()),
+            ConfigSource::User(path) => write!(f, "user file {}", path.display()),
+            ConfigSource::Project(path) => write!(f, "project file {}", path.display()),
+            ConfigSource::Profile(name) => write!(f, "profile {}", name),
+            ConfigSource::Environment(var) => write!(f, "environment {}", var),
+            ConfigSource::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// Builds a [`Config`] from layers, each overriding the ones before it:
+///
+/// 1. built-in defaults;
+/// 2. the system file, `/etc/dedup/config.{toml,json}`;
+/// 3. the user file, `$XDG_CONFIG_HOME/dedup/config.{toml,json}` (`~/.config` when unset);
+/// 4. the project file: the first `dedup.{toml,json}` (or `.dedup.*`, or the older
+///    `conf.json`) found in the working directory or its ancestors, or the file named by
+///    [`ConfigLoader::with_file`] or `DEDUP_CONFIG`;
+/// 5. the selected profile, if any;
+/// 6. `DEDUP_*` environment variables, e.g. `DEDUP_MIN_FILE_SIZE=4096`, with `__` for
+///    nested keys (`DEDUP_READ__BACKEND=mmap`);
+/// 7. command-line overrides.
+///
+/// Files may be JSON or TOML, told apart by extension. Nested tables merge key by key;
+/// lists are replaced as a whole.
+///
+/// Profiles are named tables under `profiles`, holding any settings, and may name a base
+/// with `inherits`; the base's settings apply first. One is selected by the `profile` key,
+/// which like any other can come from a file, `DEDUP_PROFILE` or the command line:
+///
+/// ```toml
+/// [profiles.home]
+/// root_paths = ["/home/me"]
+/// skip_hidden = true
+///
+/// [profiles.photos]
+/// inherits = "home"
+/// root_paths = ["/home/me/Pictures"]
+/// algorithm = "Blake3"
+/// ```
+pub struct ConfigLoader {
+    system_dir: Option<PathBuf>,
+    user_dir: Option<PathBuf>,
+    project_dir: Option<PathBuf>,
+    file: Option<PathBuf>,
+    env: Vec<(String, String)>,
+    overrides: Vec<(String, String)>,
+}
+
+/// The merged configuration and the source of each value.
+#[derive(Debug)]
+pub struct LoadedConfig {
+    pub config: Config,
+    /// The merged values, before deserializing.
+    pub values: Value,
+    /// Files that were read, in the order they were applied.
+    pub files: Vec<ConfigSource>,
+    /// Which layer set each key, by dotted path. A key missing here inherits the source
+    /// of its closest listed parent.
+    pub sources: BTreeMap<String, ConfigSource>,
+    /// Problems that did not stop loading, e.g. an I/O policy for an unscanned root.
+    pub warnings: Vec<Diagnostic>,
+    /// The selected profile.
+    pub profile: Option<String>,
+    /// Every profile defined, sorted.
+    pub profiles: Vec<String>,
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConfigLoader {
+    /// Standard locations, the process environment and the current directory.
+    pub fn new() -> Self {
+        let user_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join("dedup"));
+        ConfigLoader {
+            system_dir: Some(PathBuf::from("/etc/dedup")),
+            user_dir,
+            project_dir: env::current_dir().ok(),
+            file: None,
+            env: env::vars().filter(|(key, _)| key.starts_with(ENV_PREFIX)).collect(),
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Directories searched for the system, user and project files; `None` skips a layer.
+    pub fn with_locations(mut self, system_dir: Option<PathBuf>, user_dir: Option<PathBuf>, project_dir: Option<PathBuf>) -> Self {
+        self.system_dir = system_dir;
+        self.user_dir = user_dir;
+        self.project_dir = project_dir;
+        self
+    }
+
+    /// Uses `path` as the project file instead of searching for one.
+    pub fn with_file(mut self, path: PathBuf) -> Self {
+        self.file = Some(path);
+        self
+    }
+
+    /// Replaces the environment variables considered; only `DEDUP_*` ones are used.
+    pub fn with_env<I: IntoIterator<Item = (String, String)>>(mut self, vars: I) -> Self {
+        self.env = vars.into_iter().filter(|(key, _)| key.starts_with(ENV_PREFIX)).collect();
+        self
+    }
+
+    /// Selects a profile, overriding any `profile` set elsewhere.
+    pub fn with_profile(self, name: &str) -> Self {
+        let value = serde_json::to_string(name).expect("string serializes");
+        self.with_override(PROFILE_KEY, &value)
+    }
+
+    /// Sets a dotted key from the command line, e.g. `("read.backend", "mmap")`. The value is
+    /// read like an environment variable: JSON where it parses, a plain string otherwise, and
+    /// a path list for list-valued keys.
+    pub fn with_override(mut self, key: &str, value: &str) -> Self {
+        self.overrides.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn load(&self) -> Result<LoadedConfig, Box<dyn std::error::Error>> {
+        let mut layers = Layers::new(serde_json::to_value(Config::default())?);
+
+        if let Some(dir) = &self.system_dir
+            && let Some(path) = find_file(dir, &DIRECTORY_FILES)
+        {
+            layers.apply_file(&path, ConfigSource::System(path.clone()))?;
+        }
+        if let Some(dir) = &self.user_dir
+            && let Some(path) = find_file(dir, &DIRECTORY_FILES)
+        {
+            layers.apply_file(&path, ConfigSource::User(path.clone()))?;
+        }
+
+        let env_file = self.env.iter().find(|(key, _)| key == ENV_CONFIG_FILE).map(|(_, value)| PathBuf::from(value));
+        let project_file = match self.file.clone().or(env_file) {
+            Some(path) if path.is_file() => Some(path),
+            Some(path) => return Err(format!("Config file '{}' not found", path.display()).into()),
+            None => self.project_dir.as_deref().and_then(|dir| dir.ancestors().find_map(|dir| find_file(dir, &PROJECT_FILES))),
+        };
+        if let Some(path) = project_file {
+            layers.apply_file(&path, ConfigSource::Project(path.clone()))?;
+        }
+
+        // Sorted, so that `DEDUP_READ` is applied before `DEDUP_READ__BACKEND`.
+        let mut env: Vec<(String, &str, &str)> = self.env
+            .iter()
+            .filter(|(var, _)| var != ENV_CONFIG_FILE)
+            .map(|(var, raw)| (var[ENV_PREFIX.len()..].to_lowercase().replace("__", "."), raw.as_str(), var.as_str()))
+            .collect();
+        env.sort();
+
+        // The profile can be picked by a later layer, so settle the selection first.
+        for (key, raw, var) in env.iter().filter(|(key, _, _)| key == PROFILE_KEY) {
+            layers.set(key, raw, ConfigSource::Environment(var.to_string()))?;
+        }
+        for (key, raw) in self.overrides.iter().filter(|(key, _)| key == PROFILE_KEY) {
+            layers.set(key, raw, ConfigSource::CommandLine)?;
+        }
+        let (profile, profiles) = layers.apply_profile()?;
+
+        for (key, raw, var) in env.iter().filter(|(key, _, _)| key != PROFILE_KEY) {
+            layers.set(key, raw, ConfigSource::Environment(var.to_string()))?;
+        }
+        for (key, raw) in self.overrides.iter().filter(|(key, _)| key != PROFILE_KEY) {
+            layers.set(key, raw, ConfigSource::CommandLine)?;
+        }
+
+        let diagnostics = validation::validate(&layers.values, &layers.sources);
+        if diagnostics.iter().any(|d| d.severity == Severity::Error) {
+            return Err(Box::new(InvalidConfig(diagnostics)));
+        }
+        let config: Config = serde_json::from_value(layers.values.clone()).map_err(|e| format!("Invalid configuration: {}", e))?;
+        Ok(LoadedConfig {
+            config,
+            values: layers.values,
+            files: layers.files,
+            sources: layers.sources,
+            warnings: diagnostics,
+            profile,
+            profiles,
+        })
+    }
+}
+
+impl LoadedConfig {
+    /// Source of a dotted key, falling back to the closest parent that has one.
+    pub fn source_of(&self, key: &str) -> &ConfigSource {
+        source_for(&self.sources, key)
+    }
+
+    /// One `key = value  # source` line per setting, for `--print-config`.
+    pub fn describe(&self) -> String {
+        let mut leaves = Vec::new();
+        collect_leaves(&self.values, String::new(), &mut leaves);
+        leaves
+            .into_iter()
+            .map(|(key, value)| format!("{} = {}  # {}\n", key, value, self.source_of(&key)))
+            .collect()
+    }
+}
+
+pub(crate) fn source_for<'a>(sources: &'a BTreeMap<String, ConfigSource>, key: &str) -> &'a ConfigSource {
+    let mut key = key;
+    loop {
+        if let Some(source) = sources.get(key) {
+            return source;
+        }
+        match key.rfind('.') {
+            Some(dot) => key = &key[..dot],
+            None => return &ConfigSource::Default,
+        }
+    }
+}
+
+struct Layers {
+    values: Value,
+    files: Vec<ConfigSource>,
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl Layers {
+    fn new(defaults: Value) -> Self {
+        Layers { values: defaults, files: Vec::new(), sources: BTreeMap::new() }
+    }
+
+    fn apply_file(&mut self, path: &Path, source: ConfigSource) -> Result<(), Box<dyn std::error::Error>> {
+        let layer = read_file(path)?;
+        if !layer.is_object() {
+            return Err(format!("Config file '{}' must contain a table of settings", path.display()).into());
+        }
+        merge(&mut self.values, layer, "", &source, &mut self.sources);
+        self.files.push(source);
+        Ok(())
+    }
+
+    /// Takes the `profiles` table out of the values and merges the selected profile, base
+    /// first, over them. Returns the selected name and all defined names.
+    fn apply_profile(&mut self) -> Result<(Option<String>, Vec<String>), String> {
+        let map = self.values.as_object_mut().expect("config values are a table");
+        let profiles = match map.remove(PROFILES_KEY) {
+            None | Some(Value::Null) => Map::new(),
+            Some(Value::Object(profiles)) => profiles,
+            Some(_) => return Err(format!("'{}' must be a table of named profiles", PROFILES_KEY)),
+        };
+        let selected = match map.remove(PROFILE_KEY) {
+            None | Some(Value::Null) => None,
+            Some(Value::String(name)) => Some(name),
+            Some(other) => return Err(format!("'{}' must be a profile name, got {}", PROFILE_KEY, other)),
+        };
+        let nested = format!("{}.", PROFILES_KEY);
+        self.sources.retain(|key, _| key != PROFILES_KEY && !key.starts_with(&nested));
+        let names: Vec<String> = profiles.keys().cloned().collect();
+
+        let Some(selected) = selected else { return Ok((None, names)) };
+        let mut chain: Vec<&str> = Vec::new();
+        let mut next = Some(selected.as_str());
+        while let Some(name) = next {
+            if chain.contains(&name) {
+                chain.push(name);
+                return Err(format!("Profiles inherit from each other in a cycle: {}", chain.join(" -> ")));
+            }
+            let profile = profiles.get(name).ok_or_else(|| match chain.last() {
+                Some(child) => format!("Profile '{}' inherits from unknown profile '{}'", child, name),
+                None => format!("Unknown profile '{}'; defined profiles: {}", name, if names.is_empty() { "none".to_string() } else { names.join(", ") }),
+            })?;
+            let profile = profile.as_object().ok_or_else(|| format!("Profile '{}' must be a table of settings", name))?;
+            chain.push(name);
+            next = match profile.get(INHERITS_KEY) {
+                None => None,
+                Some(Value::String(base)) => Some(base.as_str()),
+                Some(other) => return Err(format!("'{}' in profile '{}' must be a profile name, got {}", INHERITS_KEY, name, other)),
+            };
+        }
+
+        for name in chain.iter().rev() {
+            let mut settings = profiles[*name].as_object().expect("checked above").clone();
+            settings.remove(INHERITS_KEY);
+            merge(&mut self.values, Value::Object(settings), "", &ConfigSource::Profile(name.to_string()), &mut self.sources);
+        }
+        Ok((Some(selected), names))
+    }
+
+    /// Sets one dotted key from a raw string, creating parent tables as needed.
+    fn set(&mut self, key: &str, raw: &str, source: ConfigSource) -> Result<(), String> {
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let last = parts.pop().filter(|part| !part.is_empty()).ok_or_else(|| format!("Empty config key from {}", source))?;
+
+        let mut table = &mut self.values;
+        for part in &parts {
+            let map = table.as_object_mut().ok_or_else(|| format!("Config key '{}' from {} is not inside a table", key, source))?;
+            table = map.entry(part.to_string()).or_insert_with(|| Value::Object(Map::new()));
+            if table.is_null() {
+                *table = Value::Object(Map::new());
+            }
+        }
+        let map = table.as_object_mut().ok_or_else(|| format!("Config key '{}' from {} is not inside a table", key, source))?;
+        let value = parse_raw(raw, map.get(last));
+        merge_entry(map, last, value, key, &source, &mut self.sources);
+        Ok(())
+    }
+}
+
+fn find_file(dir: &Path, names: &[&str]) -> Option<PathBuf> {
+    names.iter().map(|name| dir.join(name)).find(|path| path.is_file())
+}
+
+fn read_file(path: &Path) -> Result<Value, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read config file '{}': {}", path.display(), e))?;
+    let is_toml = path.extension().is_some_and(|ext| ext == "toml");
+    if is_toml {
+        let table: toml::Table = toml::from_str(&text).map_err(|e| format!("Invalid TOML in config file '{}': {}", path.display(), e))?;
+        Ok(serde_json::to_value(table)?)
+    } else {
+        Ok(serde_json::from_str(&text).map_err(|e| format!("Invalid JSON in config file '{}': {}", path.display(), e))?)
+    }
+}
+
+/// Reads an environment or command-line value in the shape of the one it replaces:
+/// strings stay strings, lists split like `PATH` unless given as a JSON array, anything
+/// else is JSON when it parses and a string otherwise.
+fn parse_raw(raw: &str, current: Option<&Value>) -> Value {
+    match current {
+        Some(Value::String(_)) => Value::String(raw.to_string()),
+        Some(Value::Array(_)) if !raw.trim_start().starts_with('[') => Value::Array(
+            env::split_paths(raw)
+                .filter(|p| !p.as_os_str().is_empty())
+                .map(|p| Value::String(p.to_string_lossy().into_owned()))
+                .collect(),
+        ),
+        _ => serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string())),
+    }
+}
+
+fn merge(base: &mut Value, layer: Value, prefix: &str, source: &ConfigSource, sources: &mut BTreeMap<String, ConfigSource>) {
+    let (Some(base), Value::Object(layer)) = (base.as_object_mut(), layer) else { return };
+    for (key, value) in layer {
+        let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+        merge_entry(base, &key, value, &path, source, sources);
+    }
+}
+
+/// Tables merge into tables; anything else replaces the old value and takes over the
+/// sources of everything below it.
+fn merge_entry(map: &mut Map<String, Value>, key: &str, value: Value, path: &str, source: &ConfigSource, sources: &mut BTreeMap<String, ConfigSource>) {
+    match map.get_mut(key) {
+        Some(existing) if existing.is_object() && value.is_object() => merge(existing, value, path, source, sources),
+        _ => {
+            let nested = format!("{}.", path);
+            sources.retain(|key, _| !key.starts_with(&nested));
+            sources.insert(path.to_string(), source.clone());
+            map.insert(key.to_string(), value);
+        }
+    }
+}
+
+fn collect_leaves(value: &Value, prefix: String, leaves: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let path = if prefix.is_empty() { key.clone() } else { format!("{}.{}", prefix, key) };
+                collect_leaves(value, path, leaves);
+            }
+        }
+        _ => leaves.push((prefix, value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::read_backend::ReadBackend;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_layers_override_in_order_and_record_sources() {
+        let dir = tempdir().unwrap();
+        let (system, user, project) = (dir.path().join("etc"), dir.path().join("xdg"), dir.path().join("project/sub"));
+        let (data, archive, backup) = (dir.path().join("data"), dir.path().join("archive"), dir.path().join("backup"));
+        for d in [&system, &user, &project, &data, &archive, &backup] {
+            fs::create_dir_all(d).unwrap();
+        }
+        fs::write(system.join("config.toml"), "skip_hidden = true\nmin_file_size = 1\n[read]\nbackend = \"mmap\"\nbuffer_size = 4096\n").unwrap();
+        fs::write(user.join("config.json"), r#"{"min_file_size": 10, "exclude_patterns": ["*.tmp"]}"#).unwrap();
+        // Found from a subdirectory of the project.
+        fs::write(dir.path().join("project/dedup.toml"), format!("root_paths = [{:?}]\n[read]\nfadvise = false\n", data)).unwrap();
+
+        let loaded = ConfigLoader::new()
+            .with_locations(Some(system.clone()), Some(user.clone()), Some(project))
+            .with_env([
+                ("DEDUP_MAX_FILE_SIZE".to_string(), "1000".to_string()),
+                ("DEDUP_READ__BACKEND".to_string(), "buffered".to_string()),
+                ("DEDUP_REFERENCE_PATHS".to_string(), format!("{}:{}", archive.display(), backup.display())),
+                ("HOME".to_string(), "/ignored".to_string()),
+            ])
+            .with_override("min_file_size", "100")
+            .load()
+            .unwrap();
+
+        let config = &loaded.config;
+        assert!(config.skip_hidden);
+        assert_eq!(config.min_file_size, Some(100));
+        assert_eq!(config.max_file_size, Some(1000));
+        assert_eq!(config.exclude_patterns, vec!["*.tmp"]);
+        assert_eq!(config.root_paths, vec![data]);
+        assert_eq!(config.reference_paths, vec![archive, backup]);
+        assert_eq!(config.read.backend, ReadBackend::Buffered);
+        assert_eq!(config.read.buffer_size, 4096);
+        assert!(!config.read.fadvise);
+
+        assert_eq!(loaded.files.len(), 3);
+        assert_eq!(loaded.source_of("skip_hidden"), &ConfigSource::System(system.join("config.toml")));
+        assert_eq!(loaded.source_of("exclude_patterns"), &ConfigSource::User(user.join("config.json")));
+        assert_eq!(loaded.source_of("read.fadvise"), &ConfigSource::Project(dir.path().join("project/dedup.toml")));
+        assert_eq!(loaded.source_of("read.backend"), &ConfigSource::Environment("DEDUP_READ__BACKEND".to_string()));
+        assert_eq!(loaded.source_of("min_file_size"), &ConfigSource::CommandLine);
+        assert_eq!(loaded.source_of("sampling.blocks"), &ConfigSource::Default);
+        assert!(loaded.describe().contains("read.buffer_size = 4096  # system file"));
+    }
+
+    #[test]
+    fn test_explicit_file_must_exist() {
+        let dir = tempdir().unwrap();
+        let loader = ConfigLoader::new().with_locations(None, None, None).with_env([]).with_file(dir.path().join("missing.json"));
+        assert!(loader.load().is_err());
+    }
+
+    #[test]
+    fn test_profiles_inherit_and_yield_to_environment() {
+        let dir = tempdir().unwrap();
+        let (home, nas) = (dir.path().join("home/me"), dir.path().join("mnt/nas"));
+        fs::create_dir_all(home.join("Pictures")).unwrap();
+        fs::create_dir_all(&nas).unwrap();
+        let profiles = r#"
+skip_hidden = false
+profile = "photos"
+
+[profiles.home]
+root_paths = ["/home/me"]
+skip_hidden = true
+exclude_patterns = ["*.tmp"]
+
+[profiles.photos]
+inherits = "home"
+root_paths = ["/home/me/Pictures"]
+algorithm = "Blake3"
+
+[profiles.nas-archive]
+root_paths = ["/mnt/nas"]
+"#;
+        let profiles = profiles.replace("/home/me", &home.to_string_lossy()).replace("/mnt/nas", &nas.to_string_lossy());
+        fs::write(dir.path().join("dedup.toml"), profiles).unwrap();
+        let loader = || ConfigLoader::new().with_locations(None, None, Some(dir.path().to_path_buf()));
+
+        let loaded = loader().with_env([("DEDUP_EXCLUDE_PATTERNS".to_string(), "*.bak".to_string())]).load().unwrap();
+        assert_eq!(loaded.profile.as_deref(), Some("photos"));
+        assert_eq!(loaded.profiles, vec!["home", "nas-archive", "photos"]);
+        assert_eq!(loaded.config.root_paths, vec![home.join("Pictures")]);
+        assert_eq!(loaded.config.algorithm, crate::models::HashAlgorithm::Blake3);
+        assert!(loaded.config.skip_hidden);
+        assert_eq!(loaded.config.exclude_patterns, vec!["*.bak"]);
+        assert_eq!(loaded.source_of("skip_hidden"), &ConfigSource::Profile("home".to_string()));
+        assert_eq!(loaded.source_of("root_paths"), &ConfigSource::Profile("photos".to_string()));
+        assert!(!loaded.values.as_object().unwrap().contains_key("profiles"));
+
+        let loaded = loader().with_env([("DEDUP_PROFILE".to_string(), "nas-archive".to_string())]).load().unwrap();
+        assert_eq!(loaded.config.root_paths, vec![nas]);
+        assert!(!loaded.config.skip_hidden);
+
+        let error = loader().with_env([]).with_profile("music").load().unwrap_err();
+        assert!(error.to_string().contains("defined profiles: home, nas-archive, photos"));
+    }
+
+    #[test]
+    fn test_profile_inheritance_cycle_is_an_error() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("dedup.json"), r#"{"profiles": {"a": {"inherits": "b"}, "b": {"inherits": "a"}}}"#).unwrap();
+        let error = ConfigLoader::new()
+            .with_locations(None, None, Some(dir.path().to_path_buf()))
+            .with_env([])
+            .with_profile("a")
+            .load()
+            .unwrap_err();
+        assert!(error.to_string().contains("a -> b -> a"));
+    }
+}
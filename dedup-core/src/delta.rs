@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::clustering::Cluster;
+
+const DELTA_MAGIC: &[u8; 6] = b"DDLT1\0";
+const OP_COPY: u8 = 1;
+const OP_INSERT: u8 = 2;
+pub const MANIFEST_VERSION: u32 = 1;
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Granularity of matches between a file and its base; smaller blocks find smaller common runs.
+#[derive(Debug, Clone)]
+pub struct DeltaOptions {
+    pub block_size: usize,
+    /// A file is only replaced when its patch is at most this share of its size.
+    pub max_patch_ratio: f64,
+}
+
+impl Default for DeltaOptions {
+    fn default() -> Self {
+        Self { block_size: 2048, max_patch_ratio: 0.5 }
+    }
+}
+
+/// Weak checksum of a window, updated in O(1) as the window slides (as in rsync).
+#[derive(Clone, Copy)]
+struct Rolling {
+    a: u32,
+    b: u32,
+    len: u32,
+}
+
+impl Rolling {
+    fn new(window: &[u8]) -> Self {
+        let len = window.len() as u32;
+        let (mut a, mut b) = (0u32, 0u32);
+        for (i, &byte) in window.iter().enumerate() {
+            a = a.wrapping_add(byte as u32);
+            b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+        }
+        Rolling { a: a & 0xffff, b: b & 0xffff, len }
+    }
+
+    fn roll(&mut self, out: u8, incoming: u8) {
+        self.a = self.a.wrapping_sub(out as u32).wrapping_add(incoming as u32) & 0xffff;
+        self.b = self.b.wrapping_sub(self.len.wrapping_mul(out as u32)).wrapping_add(self.a) & 0xffff;
+    }
+
+    fn value(&self) -> u32 {
+        self.a | (self.b << 16)
+    }
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_insert(out: &mut Vec<u8>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        out.push(OP_INSERT);
+        write_u64(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+}
+
+/// Patch that rebuilds `target` from `base`: copies of base ranges and inserted literal bytes.
+///
+/// Base blocks are indexed at `block_size` steps and looked up at every target offset,
+/// so shifted content still matches; matches are then extended byte by byte both ways.
+/// A `block_size` of zero is rejected with `ErrorKind::InvalidInput`.
+pub fn create_delta(base: &[u8], target: &[u8], block_size: usize) -> io::Result<Vec<u8>> {
+    if block_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "delta block size must be at least 1"));
+    }
+    let mut out = Vec::new();
+    out.extend_from_slice(DELTA_MAGIC);
+    write_u64(&mut out, base.len() as u64);
+    write_u64(&mut out, target.len() as u64);
+
+    let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+    for offset in (0..base.len().saturating_sub(block_size - 1)).step_by(block_size) {
+        index.entry(Rolling::new(&base[offset..offset + block_size]).value()).or_default().push(offset);
+    }
+
+    let mut literal = 0;
+    let mut position = 0;
+    let mut rolling = (target.len() >= block_size).then(|| Rolling::new(&target[..block_size]));
+    while let Some(window) = rolling {
+        let end = position + block_size;
+        let found = index
+            .get(&window.value())
+            .and_then(|offsets| offsets.iter().find(|&&o| base[o..o + block_size] == target[position..end]));
+        let Some(&offset) = found else {
+            rolling = (end < target.len()).then(|| {
+                let mut next = window;
+                next.roll(target[position], target[end]);
+                next
+            });
+            position += 1;
+            continue;
+        };
+
+        let mut start = position;
+        let mut base_start = offset;
+        while start > literal && base_start > 0 && target[start - 1] == base[base_start - 1] {
+            start -= 1;
+            base_start -= 1;
+        }
+        let mut len = end - start;
+        while start + len < target.len() && base_start + len < base.len() && target[start + len] == base[base_start + len] {
+            len += 1;
+        }
+        push_insert(&mut out, &target[literal..start]);
+        out.push(OP_COPY);
+        write_u64(&mut out, base_start as u64);
+        write_u64(&mut out, len as u64);
+
+        position = start + len;
+        literal = position;
+        rolling = (position + block_size <= target.len()).then(|| Rolling::new(&target[position..position + block_size]));
+    }
+    push_insert(&mut out, &target[literal..]);
+    Ok(out)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("corrupt delta: {}", message))
+}
+
+/// Rebuilds the target of a patch made by [`create_delta`] against the same `base`.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+    let mut rest = delta.strip_prefix(DELTA_MAGIC.as_slice()).ok_or_else(|| invalid("bad header"))?;
+    let read_u64 = |rest: &mut &[u8]| -> io::Result<u64> {
+        let (bytes, tail) = rest.split_first_chunk::<8>().ok_or_else(|| invalid("truncated"))?;
+        *rest = tail;
+        Ok(u64::from_le_bytes(*bytes))
+    };
+    let base_len = read_u64(&mut rest)?;
+    let target_len = read_u64(&mut rest)?;
+    if base_len != base.len() as u64 {
+        return Err(invalid("made against a base of a different size"));
+    }
+
+    let mut target = Vec::with_capacity(target_len as usize);
+    while let Some((&op, tail)) = rest.split_first() {
+        rest = tail;
+        match op {
+            OP_COPY => {
+                let offset = read_u64(&mut rest)? as usize;
+                let len = read_u64(&mut rest)? as usize;
+                let range = base.get(offset..offset.saturating_add(len)).ok_or_else(|| invalid("copy outside the base"))?;
+                target.extend_from_slice(range);
+            }
+            OP_INSERT => {
+                let len = read_u64(&mut rest)? as usize;
+                if rest.len() < len {
+                    return Err(invalid("truncated insert"));
+                }
+                let (bytes, tail) = rest.split_at(len);
+                target.extend_from_slice(bytes);
+                rest = tail;
+            }
+            _ => return Err(invalid("unknown operation")),
+        }
+    }
+    if target.len() as u64 != target_len {
+        return Err(invalid("rebuilt file has the wrong size"));
+    }
+    Ok(target)
+}
+
+fn content_hash(bytes: &[u8]) -> String {
+    blake3::hash(bytes).to_hex().to_string()
+}
+
+/// A file replaced by a patch against the archive's representative.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    pub original: PathBuf,
+    /// Patch file name, relative to the manifest's directory.
+    pub patch: PathBuf,
+    pub size: u64,
+    pub patch_size: u64,
+    /// BLAKE3 of the original content, checked after restoring.
+    pub hash: String,
+}
+
+/// Everything needed to restore the files of one archived cluster.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub version: u32,
+    pub representative: PathBuf,
+    pub representative_hash: String,
+    pub files: Vec<ArchivedFile>,
+    /// Members kept as they are because a patch would not save enough.
+    pub skipped: Vec<PathBuf>,
+}
+
+impl ArchiveManifest {
+    pub fn saved_bytes(&self) -> u64 {
+        self.files.iter().map(|f| f.size.saturating_sub(f.patch_size)).sum()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let manifest: ArchiveManifest = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if manifest.version != MANIFEST_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("manifest version {} is not supported (expected {})", manifest.version, MANIFEST_VERSION)));
+        }
+        Ok(manifest)
+    }
+
+    /// Writes to a temporary file first so a crash mid-save leaves no half-written manifest.
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            serde_json::to_writer_pretty(&mut writer, self)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+}
+
+/// Keeps the cluster's representative and replaces every other member with a patch.
+///
+/// Patches go to a directory under `archive_root` named after the representative's content,
+/// so a later run against the same representative adds to that archive instead of
+/// overwriting it. Returns the manifest and its path, which [`restore`] takes.
+///
+/// Each patch is applied back and hash-checked, and the manifest is on disk, before any
+/// original is removed; members whose patch exceeds `max_patch_ratio` are left in place.
+pub fn archive_cluster(cluster: &Cluster, archive_root: &Path, options: &DeltaOptions) -> io::Result<(PathBuf, ArchiveManifest)> {
+    if options.block_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "delta block size must be at least 1"));
+    }
+    let base = fs::read(&cluster.representative)?;
+    let representative_hash = content_hash(&base);
+    let archive_dir = archive_root.join(&representative_hash[..16]);
+    let manifest_path = archive_dir.join(MANIFEST_FILE);
+    fs::create_dir_all(&archive_dir)?;
+    let mut manifest = if manifest_path.exists() {
+        let existing = ArchiveManifest::load(&manifest_path)?;
+        if existing.representative_hash != representative_hash {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                format!("'{}' belongs to a different representative", manifest_path.display())));
+        }
+        ArchiveManifest { representative: cluster.representative.clone(), ..existing }
+    } else {
+        ArchiveManifest {
+            version: MANIFEST_VERSION,
+            representative: cluster.representative.clone(),
+            representative_hash,
+            files: Vec::new(),
+            skipped: Vec::new(),
+        }
+    };
+    // Патчи прошлых запусков остаются: новым файлам достаются следующие свободные номера
+    let mut number = 0;
+    let mut replaced = Vec::new();
+    let mut archived = Vec::new();
+
+    for original in cluster.files.iter().filter(|f| **f != cluster.representative) {
+        let target = fs::read(original)?;
+        let delta = create_delta(&base, &target, options.block_size)?;
+        manifest.skipped.retain(|skipped| skipped != original);
+        if delta.len() as f64 > target.len() as f64 * options.max_patch_ratio {
+            manifest.skipped.push(original.clone());
+            continue;
+        }
+        let hash = content_hash(&target);
+        if content_hash(&apply_delta(&base, &delta)?) != hash {
+            return Err(io::Error::other(format!("patch for '{}' does not rebuild it", original.display())));
+        }
+        while archive_dir.join(format!("{:04}.delta", number)).exists() {
+            number += 1;
+        }
+        let patch = PathBuf::from(format!("{:04}.delta", number));
+        // Файл, восстановленный после прошлого архивирования, получает новый патч
+        if let Some(previous) = manifest.files.iter().position(|f| f.original == *original) {
+            replaced.push(manifest.files.remove(previous).patch);
+        }
+        let mut writer = BufWriter::new(File::create(archive_dir.join(&patch))?);
+        writer.write_all(&delta)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        manifest.files.push(ArchivedFile {
+            original: original.clone(),
+            patch,
+            size: target.len() as u64,
+            patch_size: delta.len() as u64,
+            hash,
+        });
+        archived.push(original);
+    }
+
+    manifest.save(&manifest_path)?;
+    for original in archived {
+        fs::remove_file(original)?;
+    }
+    for patch in replaced {
+        fs::remove_file(archive_dir.join(patch))?;
+    }
+    Ok((manifest_path, manifest))
+}
+
+/// Rebuilds every file listed in the manifest at `manifest_path` and checks its hash.
+///
+/// Refuses to run when the representative changed since archiving. A file already present
+/// with the right content is left alone; one with other content is an error, not overwritten.
+pub fn restore(manifest_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let manifest = ArchiveManifest::load(manifest_path)?;
+    let archive_dir = manifest_path.parent().unwrap_or(Path::new("."));
+    let base = fs::read(&manifest.representative)?;
+    if content_hash(&base) != manifest.representative_hash {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("'{}' changed since it was archived; its patches no longer apply", manifest.representative.display())));
+    }
+
+    let mut restored = Vec::new();
+    for file in &manifest.files {
+        if file.original.exists() {
+            if content_hash(&fs::read(&file.original)?) == file.hash {
+                continue;
+            }
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                format!("'{}' exists with different content", file.original.display())));
+        }
+        let content = apply_delta(&base, &fs::read(archive_dir.join(&file.patch))?)?;
+        if content_hash(&content) != file.hash {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                format!("restored '{}' does not match its recorded hash", file.original.display())));
+        }
+        if let Some(parent) = file.original.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp_path = file.original.with_extension("restore-tmp");
+        fs::write(&tmp_path, &content)?;
+        fs::rename(&tmp_path, &file.original)?;
+        restored.push(file.original.clone());
+    }
+    Ok(restored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn pseudo_random(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                (state >> 33) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_delta_rebuilds_shifted_and_edited_content() {
+        let base = pseudo_random(64 * 1024, 1);
+        let mut target = b"inserted header".to_vec();
+        target.extend_from_slice(&base[..30_000]);
+        target.extend_from_slice(&pseudo_random(100, 2));
+        target.extend_from_slice(&base[30_100..]);
+
+        let delta = create_delta(&base, &target, 512).unwrap();
+        assert!(delta.len() < 400, "delta is {} bytes", delta.len());
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+        assert_eq!(apply_delta(&base, &create_delta(&base, b"tiny", 512).unwrap()).unwrap(), b"tiny");
+        assert!(apply_delta(&base[1..], &delta).is_err());
+        assert_eq!(create_delta(&base, &target, 0).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_archive_and_restore_round_trip() {
+        let dir = tempdir().unwrap();
+        let base = pseudo_random(32 * 1024, 3);
+        let keep = dir.path().join("v1.bin");
+        let similar = dir.path().join("v2.bin");
+        let unrelated = dir.path().join("other.bin");
+        fs::write(&keep, &base).unwrap();
+        let mut edited = base.clone();
+        edited[1000..1010].copy_from_slice(b"0123456789");
+        fs::write(&similar, &edited).unwrap();
+        fs::write(&unrelated, pseudo_random(32 * 1024, 4)).unwrap();
+
+        let cluster = Cluster {
+            files: vec![unrelated.clone(), keep.clone(), similar.clone()],
+            representative: keep.clone(),
+            min_similarity: 0.0,
+            avg_similarity: 0.5,
+        };
+        let archive = dir.path().join("archive");
+        let (manifest_path, manifest) = archive_cluster(&cluster, &archive, &DeltaOptions::default()).unwrap();
+        assert_eq!(manifest.files.len(), 1);
+        assert_eq!(manifest.skipped, vec![unrelated.clone()]);
+        assert!(!similar.exists());
+        assert!(unrelated.exists());
+        assert!(manifest.saved_bytes() > 30 * 1024);
+
+        let restored = restore(&manifest_path).unwrap();
+        assert_eq!(restored, vec![similar.clone()]);
+        assert_eq!(fs::read(&similar).unwrap(), edited);
+        assert!(restore(&manifest_path).unwrap().is_empty());
+
+        fs::write(&keep, b"changed").unwrap();
+        fs::remove_file(&similar).unwrap();
+        assert!(restore(&manifest_path).is_err());
+    }
+
+    #[test]
+    fn test_rerun_adds_to_the_archive_of_the_same_representative() {
+        let dir = tempdir().unwrap();
+        let base = pseudo_random(32 * 1024, 5);
+        let keep = dir.path().join("v1.bin");
+        fs::write(&keep, &base).unwrap();
+        let edit = |name: &str, at: usize| {
+            let path = dir.path().join(name);
+            let mut content = base.clone();
+            content[at..at + 4].copy_from_slice(b"edit");
+            fs::write(&path, &content).unwrap();
+            (path, content)
+        };
+        let cluster = |files: Vec<PathBuf>| Cluster { files, representative: keep.clone(), min_similarity: 0.9, avg_similarity: 0.9 };
+        let archive = dir.path().join("archive");
+
+        let (first, first_content) = edit("v2.bin", 100);
+        let (manifest_path, _) = archive_cluster(&cluster(vec![keep.clone(), first.clone()]), &archive, &DeltaOptions::default()).unwrap();
+        let (second, second_content) = edit("v3.bin", 20_000);
+        let (again, manifest) = archive_cluster(&cluster(vec![keep.clone(), second.clone()]), &archive, &DeltaOptions::default()).unwrap();
+        assert_eq!(again, manifest_path);
+        assert_eq!(manifest.files.iter().map(|f| f.original.clone()).collect::<Vec<_>>(), vec![first.clone(), second.clone()]);
+        assert_ne!(manifest.files[0].patch, manifest.files[1].patch);
+
+        assert_eq!(restore(&manifest_path).unwrap(), vec![first.clone(), second.clone()]);
+        assert_eq!(fs::read(&first).unwrap(), first_content);
+        assert_eq!(fs::read(&second).unwrap(), second_content);
+
+        let options = DeltaOptions { block_size: 0, ..Default::default() };
+        assert_eq!(archive_cluster(&cluster(vec![keep.clone(), first.clone()]), &archive, &options).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert!(first.exists());
+    }
+}
@@ -0,0 +1,415 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::similarity::{self, BlockOptions, MatchedRange};
+
+/// Files larger than this are always shown as a block map; line diffs grow with their size.
+pub const MAX_TEXT_BYTES: u64 = 1024 * 1024;
+/// Unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+/// Texts needing more line edits than this are shown as a block map; the diff's memory grows with its square.
+const MAX_EDIT_DISTANCE: isize = 2000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum DiffLine {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+/// One `@@ -l,n +r,m @@` section of a unified diff; line numbers start at 1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Hunk {
+    pub left_start: usize,
+    pub left_len: usize,
+    pub right_start: usize,
+    pub right_len: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// A stretch of one file that either also occurs in the other file or does not.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Region {
+    pub offset: u64,
+    pub len: u64,
+    /// Offset of the same bytes in the other file, or `None` where the files differ.
+    pub counterpart: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub enum FileDiff {
+    Text { hunks: Vec<Hunk> },
+    /// Both files split into matching and differing regions, each in its own offset order.
+    Binary { left: Vec<Region>, right: Vec<Region> },
+}
+
+/// Why two files were scored as similar.
+#[derive(Debug, Clone, Serialize)]
+pub struct PairDiff {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub left_size: u64,
+    pub right_size: u64,
+    /// Block similarity, as reported by the similar-file search.
+    pub similarity: f64,
+    pub diff: FileDiff,
+}
+
+/// Line diff when both files are small UTF-8 text, block region map otherwise. The pair is
+/// scored with `options` so the similarity matches the one the search reported.
+pub fn diff_files(left: &Path, right: &Path, options: &BlockOptions) -> io::Result<PairDiff> {
+    let left_signature = similarity::block_signature(left, options.block_size)?;
+    let right_signature = similarity::block_signature(right, options.block_size)?;
+    let pair = similarity::compare_signatures(&left_signature, &right_signature).scored_by(options.similarity);
+
+    let text = if left_signature.size <= MAX_TEXT_BYTES && right_signature.size <= MAX_TEXT_BYTES {
+        match (read_text(left)?, read_text(right)?) {
+            (Some(a), Some(b)) => Some((a, b)),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    let diff = match text.and_then(|(a, b)| line_diff(&a, &b)) {
+        Some(hunks) => FileDiff::Text { hunks },
+        None => FileDiff::Binary {
+            left: regions(&pair.ranges, left_signature.size, |r| (r.left_offset, r.right_offset)),
+            right: regions(&pair.ranges, right_signature.size, |r| (r.right_offset, r.left_offset)),
+        },
+    };
+    Ok(PairDiff {
+        left: left.to_path_buf(),
+        right: right.to_path_buf(),
+        left_size: left_signature.size,
+        right_size: right_signature.size,
+        similarity: pair.similarity,
+        diff,
+    })
+}
+
+fn read_text(path: &Path) -> io::Result<Option<String>> {
+    let bytes = fs::read(path)?;
+    if bytes.contains(&0) {
+        return Ok(None);
+    }
+    Ok(String::from_utf8(bytes).ok())
+}
+
+/// Covers `0..size` of one side with the matched ranges and the gaps between them.
+fn regions(ranges: &[MatchedRange], size: u64, side: impl Fn(&MatchedRange) -> (u64, u64)) -> Vec<Region> {
+    let mut matched: Vec<(u64, u64, u64)> = ranges
+        .iter()
+        .map(|r| {
+            let (offset, other) = side(r);
+            (offset, r.len.min(size.saturating_sub(offset)), other)
+        })
+        .collect();
+    matched.sort_unstable();
+
+    let mut regions = Vec::new();
+    let mut cursor = 0;
+    for (offset, len, other) in matched {
+        // Одни и те же байты правого файла могут совпасть с несколькими местами левого
+        let end = offset + len;
+        if end <= cursor {
+            continue;
+        }
+        if offset > cursor {
+            regions.push(Region { offset: cursor, len: offset - cursor, counterpart: None });
+        }
+        let start = offset.max(cursor);
+        regions.push(Region { offset: start, len: end - start, counterpart: Some(other + (start - offset)) });
+        cursor = end;
+    }
+    if cursor < size {
+        regions.push(Region { offset: cursor, len: size - cursor, counterpart: None });
+    }
+    regions
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Edit {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Shortest edit script between two line lists (Myers' O((N+M)D) algorithm),
+/// or `None` when it needs more than [`MAX_EDIT_DISTANCE`] edits.
+fn myers(a: &[&str], b: &[&str]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = n + m;
+    let mut v = vec![0isize; 2 * max as usize + 2];
+    // Для шага d нужны только диагонали -d-1..=d+1, так что храним окно, а не весь массив
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let window = |d: isize| ((max - d - 1).max(0) as usize, (max + d + 1) as usize);
+
+    'search: for d in 0..=max {
+        if d > MAX_EDIT_DISTANCE {
+            return None;
+        }
+        let (from, to) = window(d);
+        trace.push(v[from..=to].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + max) as usize;
+            let mut x = if k == -d || (k != d && v[index - 1] < v[index + 1]) { v[index + 1] } else { v[index - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut edits = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, saved) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let start = window(d).0 as isize;
+        let at = |k: isize| saved[(k + max - start) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            edits.push(Edit::Equal(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == prev_x {
+                edits.push(Edit::Insert(prev_y as usize));
+            } else {
+                edits.push(Edit::Delete(prev_x as usize));
+            }
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    edits.reverse();
+    Some(edits)
+}
+
+/// Unified line diff of two texts, with [`CONTEXT_LINES`] of context around each change,
+/// or `None` when they differ in too many lines to diff cheaply.
+pub fn line_diff(left: &str, right: &str) -> Option<Vec<Hunk>> {
+    let a: Vec<&str> = left.lines().collect();
+    let b: Vec<&str> = right.lines().collect();
+    let edits = myers(&a, &b)?;
+
+    let changes: Vec<usize> = edits.iter().enumerate().filter(|(_, e)| !matches!(e, Edit::Equal(..))).map(|(i, _)| i).collect();
+    let mut hunks = Vec::new();
+    let mut next = 0;
+    while next < changes.len() {
+        let start = changes[next].saturating_sub(CONTEXT_LINES);
+        let mut end = changes[next];
+        while next < changes.len() && changes[next] <= end + 2 * CONTEXT_LINES {
+            end = changes[next];
+            next += 1;
+        }
+        let end = (end + CONTEXT_LINES + 1).min(edits.len());
+
+        // Номер первой строки хунка считаем по всем правкам до него
+        let left_start = edits[..start].iter().filter(|e| !matches!(e, Edit::Insert(_))).count();
+        let right_start = edits[..start].iter().filter(|e| !matches!(e, Edit::Delete(_))).count();
+        let lines: Vec<DiffLine> = edits[start..end]
+            .iter()
+            .map(|edit| match *edit {
+                Edit::Equal(i, _) => DiffLine::Context(a[i].to_string()),
+                Edit::Delete(i) => DiffLine::Removed(a[i].to_string()),
+                Edit::Insert(j) => DiffLine::Added(b[j].to_string()),
+            })
+            .collect();
+        let left_len = lines.iter().filter(|l| !matches!(l, DiffLine::Added(_))).count();
+        let right_len = lines.iter().filter(|l| !matches!(l, DiffLine::Removed(_))).count();
+        hunks.push(Hunk {
+            left_start: if left_len == 0 { left_start } else { left_start + 1 },
+            left_len,
+            right_start: if right_len == 0 { right_start } else { right_start + 1 },
+            right_len,
+            lines,
+        });
+    }
+    Some(hunks)
+}
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const CYAN: &str = "\x1b[36m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+impl PairDiff {
+    /// Unified diff or region map for a terminal; `color` adds ANSI colours.
+    pub fn render_terminal(&self, color: bool) -> String {
+        let paint = |code: &str, text: &str| if color { format!("{}{}{}", code, text, RESET) } else { text.to_string() };
+        let mut out = String::new();
+        let _ = writeln!(out, "{}", paint(BOLD, &format!("--- {}", self.left.display())));
+        let _ = writeln!(out, "{}", paint(BOLD, &format!("+++ {}", self.right.display())));
+        let _ = writeln!(out, "сходство {:.0}%", self.similarity * 100.0);
+        match &self.diff {
+            FileDiff::Text { hunks } => {
+                for hunk in hunks {
+                    let header = format!("@@ -{},{} +{},{} @@", hunk.left_start, hunk.left_len, hunk.right_start, hunk.right_len);
+                    let _ = writeln!(out, "{}", paint(CYAN, &header));
+                    for line in &hunk.lines {
+                        let _ = match line {
+                            DiffLine::Context(text) => writeln!(out, " {}", text),
+                            DiffLine::Removed(text) => writeln!(out, "{}", paint(RED, &format!("-{}", text))),
+                            DiffLine::Added(text) => writeln!(out, "{}", paint(GREEN, &format!("+{}", text))),
+                        };
+                    }
+                }
+            }
+            FileDiff::Binary { left, right } => {
+                for (title, regions, code) in [(&self.left, left, RED), (&self.right, right, GREEN)] {
+                    let _ = writeln!(out, "{}", paint(CYAN, &format!("@@ {}", title.display())));
+                    for region in regions {
+                        let span = format!("{:08x}-{:08x} ({} bytes)", region.offset, region.offset + region.len - 1, region.len);
+                        let _ = match region.counterpart {
+                            Some(other) => writeln!(out, " {} = {:08x}", span, other),
+                            None => writeln!(out, "{}", paint(code, &format!("{}{} отличается", if code == RED { "-" } else { "+" }, span))),
+                        };
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Self-contained HTML page with the same content as [`PairDiff::render_terminal`].
+    pub fn render_html(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+        let _ = writeln!(out, "<title>{} / {}</title>", escape(&file_name(&self.left)), escape(&file_name(&self.right)));
+        out.push_str("<style>\nbody { font-family: sans-serif; }\npre { font-family: monospace; }\n\
+                      .del { background: #fdd; }\n.add { background: #dfd; }\n.hunk { color: #077; }\n\
+                      .map { display: flex; height: 1.2em; width: 100%; border: 1px solid #999; }\n\
+                      .same { background: #9c9; }\n.diff { background: #e77; }\n\
+                      td { font-family: monospace; padding: 0 1em; }\n</style>\n</head>\n<body>\n");
+        let _ = writeln!(out, "<h1>Сходство {:.0}%</h1>", self.similarity * 100.0);
+        let _ = writeln!(out, "<p><b>-</b> {} ({} bytes)<br><b>+</b> {} ({} bytes)</p>",
+                         escape(&self.left.to_string_lossy()), self.left_size,
+                         escape(&self.right.to_string_lossy()), self.right_size);
+        match &self.diff {
+            FileDiff::Text { hunks } => {
+                out.push_str("<pre>\n");
+                for hunk in hunks {
+                    let _ = writeln!(out, "<span class=\"hunk\">@@ -{},{} +{},{} @@</span>",
+                                     hunk.left_start, hunk.left_len, hunk.right_start, hunk.right_len);
+                    for line in &hunk.lines {
+                        let _ = match line {
+                            DiffLine::Context(text) => writeln!(out, " {}", escape(text)),
+                            DiffLine::Removed(text) => writeln!(out, "<span class=\"del\">-{}</span>", escape(text)),
+                            DiffLine::Added(text) => writeln!(out, "<span class=\"add\">+{}</span>", escape(text)),
+                        };
+                    }
+                }
+                out.push_str("</pre>\n");
+            }
+            FileDiff::Binary { left, right } => {
+                for (title, regions, size) in [(&self.left, left, self.left_size), (&self.right, right, self.right_size)] {
+                    let _ = writeln!(out, "<h2>{}</h2>", escape(&title.to_string_lossy()));
+                    out.push_str("<div class=\"map\">");
+                    for region in regions {
+                        let class = if region.counterpart.is_some() { "same" } else { "diff" };
+                        let _ = write!(out, "<div class=\"{}\" style=\"width: {:.4}%\" title=\"{:08x}+{}\"></div>",
+                                       class, region.len as f64 * 100.0 / size.max(1) as f64, region.offset, region.len);
+                    }
+                    out.push_str("</div>\n<table>\n");
+                    for region in regions {
+                        let other = region.counterpart.map_or_else(|| "отличается".to_string(), |o| format!("= {:08x}", o));
+                        let class = if region.counterpart.is_some() { "" } else { " class=\"del\"" };
+                        let _ = writeln!(out, "<tr{}><td>{:08x}</td><td>{}</td><td>{}</td></tr>", class, region.offset, region.len, other);
+                    }
+                    out.push_str("</table>\n");
+                }
+            }
+        }
+        out.push_str("</body>\n</html>\n");
+        out
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.to_string_lossy(), |n| n.to_string_lossy()).into_owned()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::similarity::BlockSimilarity;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_text_files_get_a_unified_diff() {
+        let dir = tempdir().unwrap();
+        let left = dir.path().join("a.txt");
+        let right = dir.path().join("b.txt");
+        let lines: Vec<String> = (1..=12).map(|i| format!("line {}", i)).collect();
+        fs::write(&left, lines.join("\n")).unwrap();
+        let mut changed = lines.clone();
+        changed[9] = "line ten <b>".to_string();
+        changed.insert(0, "header".to_string());
+        fs::write(&right, changed.join("\n")).unwrap();
+
+        let diff = diff_files(&left, &right, &BlockOptions { block_size: 512, ..Default::default() }).unwrap();
+        let FileDiff::Text { hunks } = &diff.diff else { panic!("expected a text diff") };
+        assert_eq!(hunks.len(), 2);
+        assert_eq!((hunks[0].left_start, hunks[0].left_len, hunks[0].right_start, hunks[0].right_len), (1, 3, 1, 4));
+        assert_eq!(hunks[0].lines[0], DiffLine::Added("header".into()));
+        assert_eq!((hunks[1].left_start, hunks[1].left_len, hunks[1].right_start, hunks[1].right_len), (7, 6, 8, 6));
+        assert!(hunks[1].lines.contains(&DiffLine::Removed("line 10".into())));
+
+        let plain = diff.render_terminal(false);
+        assert!(plain.contains("@@ -1,3 +1,4 @@\n+header\n line 1\n"), "{}", plain);
+        assert!(diff.render_terminal(true).contains("\x1b[31m-line 10\x1b[0m"));
+        assert!(diff.render_html().contains("<span class=\"add\">+line ten &lt;b&gt;</span>"));
+    }
+
+    #[test]
+    fn test_heavily_edited_text_falls_back_to_regions() {
+        let left: String = (0..1500).map(|i| format!("left {}\n", i)).collect();
+        let right: String = (0..1500).map(|i| format!("right {}\n", i)).collect();
+        assert!(line_diff(&left, &right).is_none());
+        assert_eq!(line_diff(&left, &left), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_binary_files_get_a_region_map() {
+        let dir = tempdir().unwrap();
+        let left = dir.path().join("a.img");
+        let right = dir.path().join("b.img");
+        let block = |fill: u8| vec![fill; 512];
+        fs::write(&left, [block(0), block(1), block(2), block(3)].concat()).unwrap();
+        fs::write(&right, [block(0), block(9), block(2), block(3), block(4)].concat()).unwrap();
+
+        let options = BlockOptions { block_size: 512, ..Default::default() };
+        let diff = diff_files(&left, &right, &options).unwrap();
+        let FileDiff::Binary { left, right } = &diff.diff else { panic!("expected a region map") };
+        assert_eq!(left, &vec![
+            Region { offset: 0, len: 512, counterpart: Some(0) },
+            Region { offset: 512, len: 512, counterpart: None },
+            Region { offset: 1024, len: 1024, counterpart: Some(1024) },
+        ]);
+        assert_eq!(right.last(), Some(&Region { offset: 2048, len: 512, counterpart: None }));
+        assert!(diff.render_terminal(false).contains("-00000200-000003ff (512 bytes) отличается"));
+        assert!(diff.render_html().contains("class=\"diff\""));
+
+        let rotated = dir.path().join("c.img");
+        fs::write(&rotated, [block(1), block(2), block(3), block(0)].concat()).unwrap();
+        assert_eq!(diff_files(&dir.path().join("a.img"), &rotated, &options).unwrap().similarity, 1.0);
+        let positional = BlockOptions { similarity: BlockSimilarity::Positional, ..options };
+        assert_eq!(diff_files(&dir.path().join("a.img"), &rotated, &positional).unwrap().similarity, 0.0);
+    }
+}
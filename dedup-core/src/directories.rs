@@ -0,0 +1,559 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::models::{DuplicateGroup, FileMetadata};
+
+/// Directories under the scan roots, rebuilt from the scanned file list.
+///
+/// Only directories that contain files (directly or below) are known, since the walk
+/// reports files only; empty subdirectories do not affect any result.
+pub(crate) struct DirectoryTree {
+    nodes: BTreeMap<PathBuf, DirectoryNode>,
+}
+
+#[derive(Default)]
+pub(crate) struct DirectoryNode {
+    pub files: Vec<FileEntry>,
+    pub subdirs: Vec<PathBuf>,
+}
+
+pub(crate) struct FileEntry {
+    pub name: OsString,
+    pub size: u64,
+    /// Index into the duplicate groups, or `None` for content found nowhere else.
+    pub group: Option<usize>,
+}
+
+impl DirectoryTree {
+    /// `roots` bound the tree; each file hangs under the longest root containing it.
+    pub(crate) fn build<'a>(files: impl IntoIterator<Item = &'a FileMetadata>, groups: &[DuplicateGroup], roots: &[PathBuf]) -> Self {
+        let group_of: HashMap<&Path, usize> = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(index, group)| group.files.iter().map(move |f| (f.path.as_path(), index)))
+            .collect();
+
+        let mut nodes: BTreeMap<PathBuf, DirectoryNode> = BTreeMap::new();
+        for file in files {
+            let Some(root) = roots.iter().filter(|r| file.path.starts_with(r)).max_by_key(|r| r.components().count()) else {
+                continue;
+            };
+            let (Some(parent), Some(name)) = (file.path.parent(), file.path.file_name()) else {
+                continue;
+            };
+            nodes.entry(parent.to_path_buf()).or_default().files.push(FileEntry {
+                name: name.to_os_string(),
+                size: file.size,
+                group: group_of.get(file.path.as_path()).copied(),
+            });
+
+            // Link the chain of parents up to the root, stopping at the first known one.
+            let mut dir = parent;
+            while dir != root.as_path() {
+                let Some(up) = dir.parent() else { break };
+                let known = nodes.contains_key(up);
+                let siblings = &mut nodes.entry(up.to_path_buf()).or_default().subdirs;
+                if !siblings.iter().any(|d| d == dir) {
+                    siblings.push(dir.to_path_buf());
+                }
+                if known {
+                    break;
+                }
+                dir = up;
+            }
+        }
+        DirectoryTree { nodes }
+    }
+
+    pub(crate) fn get(&self, dir: &Path) -> Option<&DirectoryNode> {
+        self.nodes.get(dir)
+    }
+
+    /// Every file under `dir`, recursively, with its duplicate group.
+    pub(crate) fn files_under(&self, dir: &Path) -> Vec<(PathBuf, Option<usize>)> {
+        let mut found = Vec::new();
+        let mut pending = vec![dir];
+        while let Some(dir) = pending.pop() {
+            let Some(node) = self.nodes.get(dir) else { continue };
+            found.extend(node.files.iter().map(|f| (dir.join(&f.name), f.group)));
+            pending.extend(node.subdirs.iter().map(PathBuf::as_path));
+        }
+        found.sort();
+        found
+    }
+
+    /// Directories with every subdirectory before its parent.
+    pub(crate) fn bottom_up(&self) -> Vec<&Path> {
+        let mut dirs: Vec<&Path> = self.nodes.keys().map(PathBuf::as_path).collect();
+        dirs.sort_by_key(|d| std::cmp::Reverse(d.components().count()));
+        dirs
+    }
+}
+
+/// Directories whose whole contents, names included, are identical.
+#[derive(Debug, Clone)]
+pub struct DirectoryGroup {
+    pub directories: Vec<PathBuf>,
+    pub digest: String,
+    /// Files in each directory, recursively.
+    pub file_count: usize,
+    /// Bytes in each directory, recursively.
+    pub size: u64,
+}
+
+/// Finds identical directory trees from the file-level results.
+///
+/// Each directory gets a Merkle-style digest over the sorted names and contents of its
+/// files and the digests of its subdirectories. A directory holding anything else on
+/// disk gets none: a file with no duplicate, or one the scan never hashed because the
+/// filters left it out or it could not be read. So only the files in `groups` are needed.
+/// Groups nested inside a larger reported group are left out, so a copied project shows
+/// up once rather than once per subfolder.
+pub fn find_duplicate_directories(groups: &[DuplicateGroup], roots: &[PathBuf]) -> Vec<DirectoryGroup> {
+    let tree = DirectoryTree::build(groups.iter().flat_map(|g| &g.files), groups, roots);
+    let mut digests: HashMap<&Path, (String, usize, u64)> = HashMap::new();
+
+    for dir in tree.bottom_up() {
+        let node = tree.get(dir).expect("listed directory");
+        let mut entries = Vec::with_capacity(node.files.len() + node.subdirs.len());
+        let mut file_count = node.files.len();
+        let mut size: u64 = node.files.iter().map(|f| f.size).sum();
+        let mut complete = true;
+
+        for file in &node.files {
+            match file.group {
+                Some(index) => entries.push((file.name.clone(), 'f', groups[index].hash.value.clone())),
+                None => complete = false,
+            }
+        }
+        for subdir in &node.subdirs {
+            match digests.get(subdir.as_path()) {
+                Some((digest, count, bytes)) => {
+                    entries.push((subdir.file_name().unwrap_or_default().to_os_string(), 'd', digest.clone()));
+                    file_count += count;
+                    size += bytes;
+                }
+                None => complete = false,
+            }
+        }
+        if !complete || !fully_scanned(dir, node) {
+            continue;
+        }
+
+        entries.sort();
+        let mut hasher = blake3::Hasher::new();
+        for (name, kind, digest) in &entries {
+            hasher.update(name.as_encoded_bytes());
+            hasher.update(&[0, *kind as u8, 0]);
+            hasher.update(digest.as_bytes());
+            hasher.update(b"\n");
+        }
+        digests.insert(dir, (hasher.finalize().to_hex().to_string(), file_count, size));
+    }
+
+    let mut by_digest: HashMap<&str, Vec<&Path>> = HashMap::new();
+    for (dir, (digest, _, _)) in &digests {
+        by_digest.entry(digest.as_str()).or_default().push(dir);
+    }
+
+    let mut result: Vec<DirectoryGroup> = by_digest
+        .into_iter()
+        .filter(|(_, dirs)| dirs.len() > 1)
+        .filter(|(_, dirs)| !nested_in_duplicates(dirs, &digests))
+        .map(|(digest, dirs)| {
+            let (_, file_count, size) = digests[dirs[0]];
+            let mut directories: Vec<PathBuf> = dirs.into_iter().map(Path::to_path_buf).collect();
+            directories.sort();
+            DirectoryGroup { directories, digest: digest.to_string(), file_count, size }
+        })
+        .collect();
+
+    result.sort_by(|a, b| (b.size * b.directories.len() as u64).cmp(&(a.size * a.directories.len() as u64)).then_with(|| a.directories.cmp(&b.directories)));
+    result
+}
+
+/// True when every entry on disk in `dir` is in the tree. Empty subdirectories are
+/// ignored, as in the tree itself.
+fn fully_scanned(dir: &Path, node: &DirectoryNode) -> bool {
+    let Ok(entries) = fs::read_dir(dir) else { return false };
+    let known: HashSet<&OsStr> = node
+        .files
+        .iter()
+        .map(|f| f.name.as_os_str())
+        .chain(node.subdirs.iter().filter_map(|d| d.file_name()))
+        .collect();
+    entries.into_iter().all(|entry| {
+        entry.is_ok_and(|entry| {
+            known.contains(entry.file_name().as_os_str()) || fs::read_dir(entry.path()).is_ok_and(|mut inner| inner.next().is_none())
+        })
+    })
+}
+
+/// True when the directories' parents are themselves identical, so the parents' group
+/// already covers this one. Siblings under one parent are never covered that way.
+fn nested_in_duplicates(dirs: &[&Path], digests: &HashMap<&Path, (String, usize, u64)>) -> bool {
+    let Some(parents) = dirs.iter().map(|dir| dir.parent()).collect::<Option<Vec<&Path>>>() else { return false };
+    if parents.iter().collect::<HashSet<_>>().len() < parents.len() {
+        return false;
+    }
+    let parent_digests: Option<Vec<&String>> = parents
+        .iter()
+        .map(|parent| digests.get(parent).map(|(digest, _, _)| digest))
+        .collect();
+    parent_digests.is_some_and(|digests| digests.windows(2).all(|pair| pair[0] == pair[1]))
+}
+
+/// Drops file groups whose every file lies inside a member of a directory group, for
+/// reports that list duplicate directories first.
+pub fn collapse_file_groups(groups: Vec<DuplicateGroup>, directories: &[DirectoryGroup]) -> Vec<DuplicateGroup> {
+    let covered: Vec<&Path> = directories.iter().flat_map(|g| g.directories.iter().map(PathBuf::as_path)).collect();
+    groups
+        .into_iter()
+        .filter(|group| !group.files.iter().all(|file| covered.iter().any(|dir| file.path.starts_with(dir))))
+        .collect()
+}
+
+/// Limits for [`find_similar_directories`].
+#[derive(Debug, Clone)]
+pub struct SimilarityOptions {
+    /// Smallest share of the smaller directory's content found in the other, from 0 to 1.
+    pub min_overlap: f64,
+    /// Pairs sharing fewer bytes than this are not reported.
+    pub min_shared_bytes: u64,
+    /// Content present in more directories than this (empty files, common licence texts)
+    /// is left out of the index, which keeps the number of candidate pairs bounded.
+    pub max_directories_per_content: usize,
+}
+
+impl Default for SimilarityOptions {
+    fn default() -> Self {
+        SimilarityOptions { min_overlap: 0.5, min_shared_bytes: 1, max_directories_per_content: 256 }
+    }
+}
+
+/// Two directories that share part of their content. `left` is the larger side.
+#[derive(Debug, Clone)]
+pub struct SimilarDirectories {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    /// Bytes of distinct content under each side, recursively.
+    pub left_bytes: u64,
+    pub right_bytes: u64,
+    pub shared_bytes: u64,
+    /// `shared_bytes` over the smaller side; 1.0 means `right` is contained in `left`.
+    pub overlap: f64,
+    /// Files whose content is not found anywhere under the other side.
+    pub only_left: Vec<PathBuf>,
+    pub only_right: Vec<PathBuf>,
+}
+
+/// Scores directory pairs by how much content, by bytes, they have in common.
+///
+/// Content is identified by the duplicate groups, so only files already found to be
+/// duplicates can overlap. An inverted index from each group to the directories holding
+/// it yields the candidate pairs, so directories with nothing in common are never
+/// compared. A directory and its own ancestors are not paired, and a pair is left out when
+/// the pair one level up (on either side or both) is also reported.
+pub fn find_similar_directories(
+    files: &[FileMetadata],
+    groups: &[DuplicateGroup],
+    roots: &[PathBuf],
+    options: &SimilarityOptions,
+) -> Vec<SimilarDirectories> {
+    let tree = DirectoryTree::build(files, groups, roots);
+    let dirs = tree.bottom_up();
+    let index_of: HashMap<&Path, usize> = dirs.iter().enumerate().map(|(i, dir)| (*dir, i)).collect();
+    let group_size = |group: usize| groups[group].files[0].size;
+
+    // Distinct content under each directory, merged from its subdirectories.
+    let mut contents: Vec<HashSet<usize>> = Vec::with_capacity(dirs.len());
+    let mut unique_bytes: Vec<u64> = Vec::with_capacity(dirs.len());
+    for dir in &dirs {
+        let node = tree.get(dir).expect("listed directory");
+        let mut content = HashSet::new();
+        let mut unique = 0;
+        for file in &node.files {
+            match file.group {
+                Some(group) => {
+                    content.insert(group);
+                }
+                None => unique += file.size,
+            }
+        }
+        for subdir in &node.subdirs {
+            let sub = index_of[subdir.as_path()];
+            content.extend(&contents[sub]);
+            unique += unique_bytes[sub];
+        }
+        contents.push(content);
+        unique_bytes.push(unique);
+    }
+    let total_bytes: Vec<u64> = contents
+        .iter()
+        .zip(&unique_bytes)
+        .map(|(content, unique)| unique + content.iter().map(|&g| group_size(g)).sum::<u64>())
+        .collect();
+
+    let mut postings: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (dir, content) in contents.iter().enumerate() {
+        for &group in content {
+            postings.entry(group).or_default().push(dir);
+        }
+    }
+
+    let mut shared: HashMap<(usize, usize), u64> = HashMap::new();
+    for (group, holders) in &postings {
+        if holders.len() > options.max_directories_per_content {
+            continue;
+        }
+        for (i, &a) in holders.iter().enumerate() {
+            for &b in &holders[i + 1..] {
+                if dirs[a].starts_with(dirs[b]) || dirs[b].starts_with(dirs[a]) {
+                    continue;
+                }
+                *shared.entry((a.min(b), a.max(b))).or_default() += group_size(*group);
+            }
+        }
+    }
+
+    let mut pairs: Vec<(usize, usize, u64, f64)> = shared
+        .into_iter()
+        .filter(|&(_, bytes)| bytes >= options.min_shared_bytes)
+        .map(|((a, b), bytes)| {
+            let (left, right) = if (total_bytes[a], dirs[b]) >= (total_bytes[b], dirs[a]) { (a, b) } else { (b, a) };
+            (left, right, bytes, bytes as f64 / total_bytes[right].max(1) as f64)
+        })
+        .filter(|&(_, _, _, overlap)| overlap >= options.min_overlap)
+        .collect();
+
+    let reported: HashSet<(&Path, &Path)> = pairs.iter().map(|&(a, b, _, _)| (dirs[a].min(dirs[b]), dirs[a].max(dirs[b]))).collect();
+    let covered = |a: Option<&Path>, b: Option<&Path>| match (a, b) {
+        (Some(a), Some(b)) => reported.contains(&(a.min(b), a.max(b))),
+        _ => false,
+    };
+    pairs.retain(|&(a, b, _, _)| {
+        let (a, b) = (dirs[a], dirs[b]);
+        !(covered(a.parent(), b.parent()) || covered(a.parent(), Some(b)) || covered(Some(a), b.parent()))
+    });
+    pairs.sort_by(|x, y| y.2.cmp(&x.2).then_with(|| dirs[x.0].cmp(dirs[y.0])).then_with(|| dirs[x.1].cmp(dirs[y.1])));
+
+    let only = |dir: usize, other: usize| -> Vec<PathBuf> {
+        tree.files_under(dirs[dir])
+            .into_iter()
+            .filter(|(_, group)| group.is_none_or(|g| !contents[other].contains(&g)))
+            .map(|(path, _)| path)
+            .collect()
+    };
+    pairs
+        .into_iter()
+        .map(|(left, right, shared_bytes, overlap)| SimilarDirectories {
+            left: dirs[left].to_path_buf(),
+            right: dirs[right].to_path_buf(),
+            left_bytes: total_bytes[left],
+            right_bytes: total_bytes[right],
+            shared_bytes,
+            overlap,
+            only_left: only(left, right),
+            only_right: only(right, left),
+        })
+        .collect()
+}
+
+/// Space use of one directory, counting everything below it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirectoryUsage {
+    pub path: PathBuf,
+    pub files: u64,
+    pub total_bytes: u64,
+    /// Bytes in files that have at least one identical copy, inside or outside this directory.
+    pub duplicated_bytes: u64,
+    /// Bytes in files with no copy anywhere.
+    pub unique_bytes: u64,
+    /// Bytes freed by deleting this directory without losing content: its files that
+    /// still have a copy outside it.
+    pub reclaimable_bytes: u64,
+}
+
+/// Rolls the duplicate groups up the directory tree, "du"-style, sorted by path.
+///
+/// A duplicate whose every copy lies inside the directory counts as duplicated but not
+/// reclaimable, since removing the directory would remove the content altogether.
+pub fn redundancy_rollup(files: &[FileMetadata], groups: &[DuplicateGroup], roots: &[PathBuf]) -> Vec<DirectoryUsage> {
+    let tree = DirectoryTree::build(files, groups, roots);
+    let dirs = tree.bottom_up();
+    let index_of: HashMap<&Path, usize> = dirs.iter().enumerate().map(|(i, dir)| (*dir, i)).collect();
+
+    // Copies of each group under a directory; a child's counts are handed to its parent.
+    let mut copies: Vec<HashMap<usize, u64>> = Vec::with_capacity(dirs.len());
+    let mut usage: Vec<DirectoryUsage> = Vec::with_capacity(dirs.len());
+    for dir in &dirs {
+        let node = tree.get(dir).expect("listed directory");
+        let mut counts: HashMap<usize, u64> = HashMap::new();
+        let mut entry = DirectoryUsage {
+            path: dir.to_path_buf(),
+            files: node.files.len() as u64,
+            total_bytes: 0,
+            duplicated_bytes: 0,
+            unique_bytes: 0,
+            reclaimable_bytes: 0,
+        };
+        for file in &node.files {
+            entry.total_bytes += file.size;
+            match file.group {
+                Some(group) => *counts.entry(group).or_default() += 1,
+                None => entry.unique_bytes += file.size,
+            }
+        }
+        for subdir in &node.subdirs {
+            let sub = index_of[subdir.as_path()];
+            for (group, count) in std::mem::take(&mut copies[sub]) {
+                *counts.entry(group).or_default() += count;
+            }
+            entry.files += usage[sub].files;
+            entry.total_bytes += usage[sub].total_bytes;
+            entry.unique_bytes += usage[sub].unique_bytes;
+        }
+        for (&group, &count) in &counts {
+            let bytes = count * groups[group].files[0].size;
+            entry.duplicated_bytes += bytes;
+            if count < groups[group].files.len() as u64 {
+                entry.reclaimable_bytes += bytes;
+            }
+        }
+        copies.push(counts);
+        usage.push(entry);
+    }
+
+    usage.sort_by(|a, b| a.path.cmp(&b.path));
+    usage
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::duplicates::DuplicateFinder;
+    use crate::models::HashAlgorithm;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn write_tree(root: &Path, files: &[(&str, &str)]) -> Vec<FileMetadata> {
+        files
+            .iter()
+            .map(|(name, content)| {
+                let path = root.join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, content).unwrap();
+                FileMetadata { path, size: content.len() as u64, hash: None, modified: None, created: None }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_trees_are_reported_once_and_collapse_file_groups() {
+        let dir = tempdir().unwrap();
+        let project = [("main.rs", "fn main() {}"), ("src/lib.rs", "pub mod a;"), ("src/a.rs", "pub fn a() {}")];
+        let mut files = write_tree(&dir.path().join("project"), &project);
+        files.extend(write_tree(&dir.path().join("backup/project-copy"), &project));
+        // Same files, but one extra: not identical to the others.
+        files.extend(write_tree(&dir.path().join("variant"), &project));
+        files.extend(write_tree(&dir.path().join("variant"), &[("notes.txt", "only here")]));
+        files.extend(write_tree(&dir.path().join("loose"), &[("x.txt", "loose copy"), ("y.txt", "loose copy")]));
+
+        let report = DuplicateFinder::new(HashAlgorithm::Blake3).find_duplicates(files.clone());
+        let directories = find_duplicate_directories(&report.groups, &[dir.path().to_path_buf()]);
+
+        // The two full copies once, plus `src`, which `variant` shares with them; the copies'
+        // own `src` folders are not reported again on their own.
+        assert_eq!(directories.len(), 2);
+        assert_eq!(directories[0].directories, vec![dir.path().join("backup/project-copy"), dir.path().join("project")]);
+        assert_eq!(directories[0].file_count, 3);
+        assert_eq!(directories[1].directories.len(), 3);
+        assert!(directories[1].directories.iter().all(|d| d.ends_with("src")));
+
+        let collapsed = collapse_file_groups(report.groups.clone(), &directories);
+        assert_eq!(report.groups.len(), 4);
+        // `variant/main.rs` is outside every identical directory, so its group stays.
+        assert_eq!(collapsed.len(), 2);
+
+        let without_variant: Vec<FileMetadata> = files.into_iter().filter(|f| !f.path.starts_with(dir.path().join("variant"))).collect();
+        let report = DuplicateFinder::new(HashAlgorithm::Blake3).find_duplicates(without_variant);
+        let directories = find_duplicate_directories(&report.groups, &[dir.path().to_path_buf()]);
+        let collapsed = collapse_file_groups(report.groups, &directories);
+        assert_eq!(collapsed.len(), 1);
+        assert!(collapsed[0].files[0].path.starts_with(dir.path().join("loose")));
+    }
+
+    #[test]
+    fn test_directories_with_unscanned_entries_are_not_identical() {
+        let dir = tempdir().unwrap();
+        let project = [("main.rs", "fn main() {}"), ("src/lib.rs", "pub mod a;")];
+        let mut files = write_tree(&dir.path().join("project"), &project);
+        files.extend(write_tree(&dir.path().join("copy"), &project));
+        fs::create_dir_all(dir.path().join("copy/empty")).unwrap();
+        let roots = [dir.path().to_path_buf()];
+
+        let report = DuplicateFinder::new(HashAlgorithm::Blake3).find_duplicates(files);
+        assert_eq!(find_duplicate_directories(&report.groups, &roots)[0].directories.len(), 2);
+
+        // Left out of the scan, e.g. by an exclude pattern: the copies may differ there.
+        write_tree(&dir.path().join("copy/src"), &[(".secret", "not scanned")]);
+        let directories = find_duplicate_directories(&report.groups, &roots);
+        assert!(directories.iter().all(|g| !g.directories.contains(&dir.path().join("copy"))), "{:?}", directories);
+    }
+
+    #[test]
+    fn test_similar_directories_report_overlap_and_unique_files() {
+        let dir = tempdir().unwrap();
+        let photos: Vec<(String, String)> = (0..20).map(|i| (format!("2023/img{i}.jpg"), format!("photo number {i:04}"))).collect();
+        let photos: Vec<(&str, &str)> = photos.iter().map(|(n, c)| (n.as_str(), c.as_str())).collect();
+        let mut files = write_tree(&dir.path().join("photos"), &photos);
+        // An old backup holding 19 of the photos plus one edit of its own.
+        files.extend(write_tree(&dir.path().join("photos_backup_old"), &photos[..19]));
+        files.extend(write_tree(&dir.path().join("photos_backup_old"), &[("2023/edited.jpg", "photo edited 0000")]));
+        files.extend(write_tree(&dir.path().join("music"), &[("song.mp3", "unrelated audio"), ("img0.jpg", "photo number 0000")]));
+
+        let report = DuplicateFinder::new(HashAlgorithm::Blake3).find_duplicates(files.clone());
+        let similar = find_similar_directories(&files, &report.groups, &[dir.path().to_path_buf()], &SimilarityOptions { min_overlap: 0.9, ..Default::default() });
+
+        // Reported once for the top-level pair, not again for their `2023` folders.
+        assert_eq!(similar.len(), 1);
+        let pair = &similar[0];
+        assert_eq!(pair.left, dir.path().join("photos"));
+        assert_eq!(pair.right, dir.path().join("photos_backup_old"));
+        assert_eq!(pair.shared_bytes, 19 * 17);
+        assert!((pair.overlap - 0.95).abs() < 1e-9);
+        assert_eq!(pair.only_left, vec![dir.path().join("photos/2023/img19.jpg")]);
+        assert_eq!(pair.only_right, vec![dir.path().join("photos_backup_old/2023/edited.jpg")]);
+    }
+
+    #[test]
+    fn test_rollup_separates_duplicated_unique_and_reclaimable() {
+        let dir = tempdir().unwrap();
+        let mut files = write_tree(&dir.path().join("work"), &[
+            ("report.doc", "shared report"),   // also in downloads
+            ("notes.txt", "unique notes"),
+            ("a/draft.txt", "draft text"),     // two copies, both under work
+            ("b/draft.txt", "draft text"),
+        ]);
+        files.extend(write_tree(&dir.path().join("downloads"), &[("report (1).doc", "shared report")]));
+
+        let report = DuplicateFinder::new(HashAlgorithm::Blake3).find_duplicates(files.clone());
+        let usage = redundancy_rollup(&files, &report.groups, &[dir.path().to_path_buf()]);
+        let of = |path: &str| usage.iter().find(|u| u.path == dir.path().join(path)).unwrap();
+
+        let work = of("work");
+        assert_eq!(work.files, 4);
+        assert_eq!(work.total_bytes, 13 + 12 + 10 + 10);
+        assert_eq!(work.duplicated_bytes, 13 + 10 + 10);
+        assert_eq!(work.unique_bytes, 12);
+        // Only the report survives elsewhere; both drafts would be lost with `work`.
+        assert_eq!(work.reclaimable_bytes, 13);
+        assert_eq!(of("work/a").reclaimable_bytes, 10);
+        assert_eq!(of("downloads").reclaimable_bytes, 13);
+
+        let root = usage.iter().find(|u| u.path == dir.path()).unwrap();
+        assert_eq!(root.files, 5);
+        assert_eq!(root.reclaimable_bytes, 0);
+    }
+}
@@ -0,0 +1,587 @@
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use rayon::prelude::*;
+use crate::cancel::CancellationToken;
+use crate::error::{ScanError, ScanStage};
+use crate::events::EventBus;
+use crate::hasher::{self, SamplingOptions};
+use crate::io_scheduler::{IoScheduler, Throttle};
+use crate::read_backend::ReadOptions;
+use crate::reference::ReferenceSet;
+use crate::models::{DuplicateGroup, FileHash, FileMetadata, HashAlgorithm, VerificationMethod};
+use crate::progress::{ProgressTracker, TrackedStage};
+use crate::scanner::ScanItem;
+use crate::verify;
+
+const PARTIAL_HASH_BYTES: u64 = 4096;
+const CANDIDATE_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct DuplicateReport {
+    pub groups: Vec<DuplicateGroup>,
+    pub errors: Vec<ScanError>,
+    /// Stages that were cancelled before finishing. Groups in a partial report are still
+    /// confirmed duplicates, but files that were never reached may be missing from them.
+    pub incomplete_stages: Vec<ScanStage>,
+}
+
+impl DuplicateReport {
+    pub fn is_complete(&self) -> bool {
+        self.incomplete_stages.is_empty()
+    }
+}
+
+/// Hashes computed by an earlier run, consulted before reading a file and updated after.
+pub trait HashStore: Send + Sync {
+    fn lookup(&self, file: &FileMetadata, algorithm: HashAlgorithm, partial: bool) -> Option<FileHash>;
+    fn record(&self, file: &FileMetadata, hash: &FileHash);
+}
+
+pub struct DuplicateFinder {
+    algorithm: HashAlgorithm,
+    cancel: CancellationToken,
+    store: Option<Arc<dyn HashStore>>,
+    events: Arc<EventBus>,
+    scheduler: Option<Arc<IoScheduler>>,
+    read_options: ReadOptions,
+    sampling: Option<SamplingOptions>,
+    verify_bytes: bool,
+    references: Option<Arc<ReferenceSet>>,
+}
+
+/// What one hashing pass reads from each file.
+#[derive(Clone, Copy)]
+enum HashPass<'a> {
+    Prefix,
+    Sample(&'a SamplingOptions),
+    Full,
+}
+
+impl HashPass<'_> {
+    fn bytes(&self, size: u64) -> u64 {
+        match self {
+            HashPass::Prefix => size.min(PARTIAL_HASH_BYTES),
+            HashPass::Sample(sampling) => sampling.sampled_bytes(size),
+            HashPass::Full => size,
+        }
+    }
+}
+
+impl DuplicateFinder {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        DuplicateFinder { algorithm, cancel: CancellationToken::new(), store: None, events: Arc::new(EventBus::new()), scheduler: None, read_options: ReadOptions::default(), sampling: Some(SamplingOptions::default()), verify_bytes: false, references: None }
+    }
+
+    pub fn with_hash_store(mut self, store: Arc<dyn HashStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Stops consuming input and hashing once `cancel` is triggered.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Publishes `Hashing` progress for the prefix and full-hash passes to `events`,
+    /// typically the scanner's bus so one listener sees the whole run.
+    pub fn with_events(mut self, events: Arc<EventBus>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Reads files in the order and at the pace `scheduler` sets. Ordering needs the whole
+    /// candidate set, so prefix hashing then waits for the walk instead of overlapping it.
+    pub fn with_io_scheduler(mut self, scheduler: Arc<IoScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    pub fn with_read_options(mut self, options: ReadOptions) -> Self {
+        self.read_options = options;
+        self
+    }
+
+    /// Sets the sampling pass for large files, or turns it off with `None`. On by default.
+    pub fn with_sampling(mut self, sampling: Option<SamplingOptions>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    /// Compares every group byte for byte after hashing and splits it where contents differ,
+    /// so no report depends on hash equality alone. Off by default.
+    pub fn with_verification(mut self, verify_bytes: bool) -> Self {
+        self.verify_bytes = verify_bytes;
+        self
+    }
+
+    /// Reference-set mode: only groups with files both inside and outside `references` are
+    /// followed up, so duplicates among targets alone or the archive alone cost no reads
+    /// past the prefix. Use [`ReferenceSet::matches`] to split the resulting groups.
+    pub fn with_reference_set(mut self, references: Arc<ReferenceSet>) -> Self {
+        self.references = Some(references).filter(|r| !r.is_empty());
+        self
+    }
+
+    /// Groups files by size, then by a hash of the first block, then (for large files) by a
+    /// hash of sampled blocks, then by the full hash. Only full-hash matches are reported,
+    /// optionally confirmed byte for byte.
+    /// Files that fail to hash are reported in `errors` instead of being dropped.
+    pub fn find_duplicates(&self, files: Vec<FileMetadata>) -> DuplicateReport {
+        self.find_duplicates_streaming(files.into_iter().map(Ok))
+    }
+
+    /// Same as [`DuplicateFinder::find_duplicates`], but consumes entries as they arrive,
+    /// e.g. from [`crate::scanner::Scanner::stream`].
+    ///
+    /// A file is sent for prefix hashing as soon as a second file of the same size shows up,
+    /// so hashing overlaps with the walk. Scan errors are passed through to the report.
+    pub fn find_duplicates_streaming<I>(&self, items: I) -> DuplicateReport
+    where
+        I: IntoIterator<Item = ScanItem>,
+        I::IntoIter: Send,
+    {
+        let items = items.into_iter();
+        let (sender, receiver) = mpsc::sync_channel::<FileMetadata>(CANDIDATE_BUFFER);
+
+        let mut incomplete_stages = Vec::new();
+        let prefix_progress = &ProgressTracker::new(&self.events, TrackedStage::Hashing { partial: true });
+
+        let (prefixed, (mut errors, input_exhausted)) = thread::scope(|scope| {
+            let bucketer = scope.spawn(move || {
+                let mut errors = Vec::new();
+                let mut first_of_size: HashMap<u64, Option<FileMetadata>> = HashMap::new();
+
+                for item in items {
+                    if self.cancel.is_cancelled() {
+                        return (errors, false);
+                    }
+                    let file = match item {
+                        Ok(file) => file,
+                        Err(e) => {
+                            errors.push(e);
+                            continue;
+                        }
+                    };
+                    match first_of_size.entry(file.size) {
+                        Entry::Vacant(slot) => {
+                            slot.insert(Some(file));
+                        }
+                        Entry::Occupied(mut slot) => {
+                            let first = slot.get_mut().take();
+                            for candidate in first.into_iter().chain(Some(file)) {
+                                prefix_progress.add_work(1, HashPass::Prefix.bytes(candidate.size));
+                                if sender.send(candidate).is_err() {
+                                    return (errors, false);
+                                }
+                            }
+                        }
+                    }
+                }
+                (errors, true)
+            });
+
+            let prefixed = match &self.scheduler {
+                Some(scheduler) => self.hash_scheduled(scheduler, receiver.into_iter().collect(), HashPass::Prefix, prefix_progress),
+                None => self.hash_all(receiver.into_iter().par_bridge(), HashPass::Prefix, prefix_progress),
+            };
+            let scan_errors = bucketer.join().expect("size bucketing thread panicked");
+            (prefixed, scan_errors)
+        });
+
+        let (prefixed, prefix_errors, prefix_complete) = prefixed;
+        prefix_progress.finish();
+        errors.extend(prefix_errors);
+        if !input_exhausted {
+            incomplete_stages.push(ScanStage::Walk);
+        }
+
+        let mut groups: Vec<DuplicateGroup> = Vec::new();
+        let mut full_candidates = Vec::new();
+        for mut group in self.candidate_groups(prefixed) {
+            // The prefix already covers these files entirely.
+            if group[0].size <= PARTIAL_HASH_BYTES {
+                for file in &mut group {
+                    if let Some(hash) = &mut file.hash {
+                        hash.partial = false;
+                    }
+                }
+                groups.push(make_group(group));
+            } else {
+                full_candidates.extend(group);
+            }
+        }
+
+        let mut sample_complete = true;
+        if let Some(sampling) = &self.sampling {
+            let (to_sample, rest): (Vec<_>, Vec<_>) = full_candidates
+                .into_iter()
+                .partition(|f| f.size >= sampling.min_file_size);
+            let (sampled, sample_errors, complete) = self.run_pass(to_sample, HashPass::Sample(sampling), TrackedStage::Processing("sampling".to_string()));
+            errors.extend(sample_errors);
+            sample_complete = complete;
+            full_candidates = rest;
+            full_candidates.extend(self.candidate_groups(sampled).into_iter().flatten());
+        }
+
+        let (hashed, hash_errors, full_complete) = self.run_pass(full_candidates, HashPass::Full, TrackedStage::Hashing { partial: false });
+        errors.extend(hash_errors);
+        if !(input_exhausted && prefix_complete && sample_complete && full_complete) {
+            incomplete_stages.push(ScanStage::Hashing);
+        }
+        groups.extend(self.candidate_groups(hashed).into_iter().map(make_group));
+
+        if self.verify_bytes {
+            let (verified, verify_errors, verify_complete) = self.verify_groups(groups);
+            groups = verified;
+            groups.retain(|group| self.is_relevant(&group.files));
+            errors.extend(verify_errors);
+            if !verify_complete {
+                incomplete_stages.push(ScanStage::Verification);
+            }
+        }
+
+        groups.sort_by(|a, b| b.total_size.cmp(&a.total_size).then_with(|| a.files[0].path.cmp(&b.files[0].path)));
+
+        DuplicateReport { groups, errors, incomplete_stages }
+    }
+
+    /// Files with the same size and latest hash, in groups still worth pursuing.
+    fn candidate_groups(&self, files: Vec<FileMetadata>) -> Vec<Vec<FileMetadata>> {
+        let mut groups = group_by(files, |f| (f.size, f.hash.clone()));
+        groups.retain(|group| self.is_relevant(group));
+        groups
+    }
+
+    fn is_relevant(&self, files: &[FileMetadata]) -> bool {
+        self.references.as_ref().is_none_or(|references| references.is_mixed(files))
+    }
+
+    /// Splits every group into byte-identical subsets. Groups left unsettled by cancellation
+    /// are kept as they were, still marked as hash-verified.
+    fn verify_groups(&self, groups: Vec<DuplicateGroup>) -> (Vec<DuplicateGroup>, Vec<ScanError>, bool) {
+        let progress = ProgressTracker::new(&self.events, TrackedStage::Processing("verification".to_string()));
+        progress.add_work(
+            groups.iter().map(|g| g.files.len() as u64).sum(),
+            groups.iter().map(|g| g.total_size).sum(),
+        );
+
+        let results: Vec<(Vec<DuplicateGroup>, Vec<ScanError>, bool)> = groups
+            .into_par_iter()
+            .map(|group| {
+                let first = group.files[0].path.clone();
+                let on_read = |bytes| progress.advance(bytes, &first);
+                let outcome = verify::verify_group(group.files.clone(), self.read_options.buffer_size, &self.cancel, &on_read);
+                for file in &group.files {
+                    progress.file_done(&file.path);
+                }
+                match outcome {
+                    Some(verified) => {
+                        let split = verified.groups.into_iter().map(|files| DuplicateGroup {
+                            verification: VerificationMethod::ByteForByte,
+                            ..make_group(files)
+                        });
+                        (split.collect(), verified.errors, true)
+                    }
+                    None => (vec![group], Vec::new(), false),
+                }
+            })
+            .collect();
+        progress.finish();
+
+        let mut groups = Vec::new();
+        let mut errors = Vec::new();
+        let mut complete = true;
+        for (verified, verify_errors, settled) in results {
+            groups.extend(verified);
+            errors.extend(verify_errors);
+            complete &= settled;
+        }
+        (groups, errors, complete)
+    }
+
+    /// Runs one pass over a known set of files, with its own progress reporting.
+    fn run_pass(&self, files: Vec<FileMetadata>, pass: HashPass, stage: TrackedStage) -> (Vec<FileMetadata>, Vec<ScanError>, bool) {
+        let progress = ProgressTracker::new(&self.events, stage);
+        progress.add_work(files.len() as u64, files.iter().map(|f| pass.bytes(f.size)).sum());
+        let result = match &self.scheduler {
+            Some(scheduler) => self.hash_scheduled(scheduler, files, pass, &progress),
+            None => self.hash_all(files.into_par_iter(), pass, &progress),
+        };
+        progress.finish();
+        result
+    }
+
+    /// Hashes every file in parallel, splitting the successes from the failures. Files
+    /// skipped because of cancellation are neither; the flag reports whether any were.
+    fn hash_all<I>(&self, files: I, pass: HashPass, progress: &ProgressTracker) -> (Vec<FileMetadata>, Vec<ScanError>, bool)
+    where
+        I: ParallelIterator<Item = FileMetadata>,
+    {
+        let results = files.map(|file| self.hash_one(file, pass, progress, None)).collect();
+        split_results(results)
+    }
+
+    /// Like [`DuplicateFinder::hash_all`], but each device queue from the scheduler is
+    /// drained in order by its own set of readers.
+    fn hash_scheduled(&self, scheduler: &IoScheduler, files: Vec<FileMetadata>, pass: HashPass, progress: &ProgressTracker) -> (Vec<FileMetadata>, Vec<ScanError>, bool) {
+        let queues = scheduler.plan(files);
+        let results = Mutex::new(Vec::new());
+
+        rayon::scope(|scope| {
+            for queue in &queues {
+                let next = Arc::new(AtomicUsize::new(0));
+                for _ in 0..queue.readers.min(queue.files.len()) {
+                    let next = Arc::clone(&next);
+                    let results = &results;
+                    scope.spawn(move |_| {
+                        while let Some((file, throttle)) = queue.files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                            let result = self.hash_one(file.clone(), pass, progress, *throttle);
+                            results.lock().unwrap().push(result);
+                        }
+                    });
+                }
+            }
+        });
+
+        split_results(results.into_inner().unwrap())
+    }
+
+    /// `None` when the file was skipped or abandoned because of cancellation.
+    fn hash_one(&self, mut file: FileMetadata, pass: HashPass, progress: &ProgressTracker, throttle: Option<&Throttle>) -> Option<ScanItem> {
+        if self.cancel.is_cancelled() {
+            return None;
+        }
+        let result = self.hash_with_store(&file, pass, progress, throttle);
+        if result.is_err() && self.cancel.is_cancelled() {
+            return None;
+        }
+        progress.file_done(&file.path);
+        match result {
+            Ok(file_hash) => {
+                file.hash = Some(file_hash);
+                Some(Ok(file))
+            }
+            Err(e) => Some(Err(ScanError::from_io(&file.path, ScanStage::Hashing, &e))),
+        }
+    }
+
+    /// Sample hashes are cheap and not stored, so the store only ever holds one partial
+    /// (prefix) and one full hash per file.
+    fn hash_with_store(&self, file: &FileMetadata, pass: HashPass, progress: &ProgressTracker, throttle: Option<&Throttle>) -> std::io::Result<FileHash> {
+        let on_read = |bytes| {
+            if let Some(throttle) = throttle {
+                throttle.consume(bytes);
+            }
+            progress.advance(bytes, &file.path)
+        };
+        let hash = || match pass {
+            HashPass::Prefix => hasher::hash_file_observed(&file.path, self.algorithm, Some(PARTIAL_HASH_BYTES), &self.read_options, &self.cancel, &on_read),
+            HashPass::Sample(sampling) => hasher::hash_file_sampled(&file.path, self.algorithm, file.size, sampling, &self.read_options, &self.cancel, &on_read),
+            HashPass::Full => hasher::hash_file_observed(&file.path, self.algorithm, None, &self.read_options, &self.cancel, &on_read),
+        };
+        let Some(store) = self.store.as_ref().filter(|_| !matches!(pass, HashPass::Sample(_))) else {
+            return hash();
+        };
+        if let Some(known) = store.lookup(file, self.algorithm, matches!(pass, HashPass::Prefix)) {
+            // Count stored hashes as read so totals and the ETA stay consistent.
+            progress.advance(pass.bytes(file.size), &file.path);
+            return Ok(known);
+        }
+        let computed = hash()?;
+        store.record(file, &computed);
+        Ok(computed)
+    }
+}
+
+fn split_results(results: Vec<Option<ScanItem>>) -> (Vec<FileMetadata>, Vec<ScanError>, bool) {
+    let mut hashed = Vec::with_capacity(results.len());
+    let mut errors = Vec::new();
+    let mut complete = true;
+    for result in results {
+        match result {
+            Some(Ok(file)) => hashed.push(file),
+            Some(Err(e)) => errors.push(e),
+            None => complete = false,
+        }
+    }
+    (hashed, errors, complete)
+}
+
+fn group_by<K, F>(files: Vec<FileMetadata>, key: F) -> Vec<Vec<FileMetadata>>
+where
+    K: std::hash::Hash + Eq,
+    F: Fn(&FileMetadata) -> K,
+{
+    let mut map: HashMap<K, Vec<FileMetadata>> = HashMap::new();
+    for file in files {
+        map.entry(key(&file)).or_default().push(file);
+    }
+    map.into_values().filter(|g| g.len() > 1).collect()
+}
+
+fn make_group(mut files: Vec<FileMetadata>) -> DuplicateGroup {
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    let hash = files[0].hash.clone().expect("grouped files are hashed");
+    let total_size = files.iter().map(|f| f.size).sum();
+    DuplicateGroup { files, total_size, hash, verification: VerificationMethod::FullHash }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn metadata(path: &Path) -> FileMetadata {
+        FileMetadata {
+            path: path.to_path_buf(),
+            size: fs::metadata(path).map(|m| m.len()).unwrap_or(0),
+            hash: None,
+            modified: None,
+            created: None,
+        }
+    }
+
+    #[test]
+    fn test_find_duplicates_groups_identical_files() {
+        let dir = tempdir().unwrap();
+        let big = vec![7u8; 10_000];
+        let mut big_other = big.clone();
+        big_other[9_999] = 8;
+
+        fs::write(dir.path().join("a.txt"), "identical content").unwrap();
+        fs::write(dir.path().join("b.txt"), "identical content").unwrap();
+        fs::write(dir.path().join("c.txt"), "different content").unwrap();
+        fs::write(dir.path().join("big1.bin"), &big).unwrap();
+        fs::write(dir.path().join("big2.bin"), &big).unwrap();
+        fs::write(dir.path().join("big3.bin"), &big_other).unwrap();
+
+        let files = ["a.txt", "b.txt", "c.txt", "big1.bin", "big2.bin", "big3.bin"]
+            .iter()
+            .map(|name| metadata(&dir.path().join(name)))
+            .collect();
+
+        let report = DuplicateFinder::new(HashAlgorithm::SHA256).find_duplicates(files);
+        assert!(report.errors.is_empty());
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!(report.groups[0].total_size, 20_000);
+        assert!(report.groups[0].files[0].path.ends_with("big1.bin"));
+        assert!(report.groups[1].files[1].path.ends_with("b.txt"));
+    }
+
+    #[test]
+    fn test_find_duplicates_reports_unreadable_files() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "identical content").unwrap();
+        fs::write(dir.path().join("b.txt"), "identical content").unwrap();
+
+        let mut files: Vec<FileMetadata> = ["a.txt", "b.txt"]
+            .iter()
+            .map(|name| metadata(&dir.path().join(name)))
+            .collect();
+        let mut vanished = metadata(&dir.path().join("a.txt"));
+        vanished.path = dir.path().join("gone.txt");
+        files.push(vanished);
+
+        let report = DuplicateFinder::new(HashAlgorithm::SHA256).find_duplicates(files);
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].kind, crate::error::ScanErrorKind::Vanished);
+        assert_eq!(report.errors[0].stage, ScanStage::Hashing);
+    }
+
+    #[test]
+    fn test_scheduled_run_finds_the_same_groups() {
+        let dir = tempdir().unwrap();
+        let big = vec![3u8; 10_000];
+        fs::write(dir.path().join("a.bin"), &big).unwrap();
+        fs::write(dir.path().join("b.bin"), &big).unwrap();
+        fs::write(dir.path().join("c.txt"), "identical content").unwrap();
+        fs::write(dir.path().join("d.txt"), "identical content").unwrap();
+        let files = ["a.bin", "b.bin", "c.txt", "d.txt"].iter().map(|name| metadata(&dir.path().join(name))).collect();
+
+        let scheduler = IoScheduler::new(vec![crate::config::IoPolicy {
+            root: dir.path().to_path_buf(),
+            read_order: crate::config::ReadOrder::Extent,
+            readers_per_device: Some(1),
+            max_mb_per_sec: Some(100.0),
+        }]);
+        let report = DuplicateFinder::new(HashAlgorithm::XXH3)
+            .with_io_scheduler(Arc::new(scheduler))
+            .find_duplicates(files);
+
+        assert!(report.is_complete());
+        assert_eq!(report.groups.len(), 2);
+        assert!(report.groups[0].files[0].path.ends_with("a.bin"));
+        assert!(!report.groups[0].hash.partial);
+    }
+
+    #[test]
+    fn test_matching_samples_still_need_a_full_hash() {
+        let dir = tempdir().unwrap();
+        let base: Vec<u8> = (0..100_000u32).map(|i| (i % 253) as u8).collect();
+        let mut same_samples = base.clone();
+        // Between the sampled blocks, so only the full hash can tell them apart.
+        same_samples[5_000] ^= 1;
+        let mut other_samples = base.clone();
+        other_samples[50_000] ^= 1;
+        for (name, content) in [("a.bin", &base), ("b.bin", &base), ("c.bin", &same_samples), ("d.bin", &other_samples)] {
+            fs::write(dir.path().join(name), content).unwrap();
+        }
+        let files = ["a.bin", "b.bin", "c.bin", "d.bin"].iter().map(|name| metadata(&dir.path().join(name))).collect();
+
+        let sampling = SamplingOptions { min_file_size: 0, block_size: 1_000, blocks: 3 };
+        assert!(sampling.ranges(100_000).iter().all(|&(offset, length)| !(offset..offset + length).contains(&5_000)));
+        assert!(sampling.ranges(100_000).iter().any(|&(offset, length)| (offset..offset + length).contains(&50_000)));
+
+        let report = DuplicateFinder::new(HashAlgorithm::Blake3)
+            .with_sampling(Some(sampling))
+            .find_duplicates(files);
+
+        assert!(report.is_complete());
+        assert_eq!(report.groups.len(), 1);
+        let names: Vec<_> = report.groups[0].files.iter().map(|f| f.path.file_name().unwrap()).collect();
+        assert_eq!(names, ["a.bin", "b.bin"]);
+        assert!(!report.groups[0].hash.partial);
+    }
+
+    #[test]
+    fn test_verification_is_recorded() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "identical content").unwrap();
+        fs::write(dir.path().join("b.txt"), "identical content").unwrap();
+        let files: Vec<FileMetadata> = ["a.txt", "b.txt"].iter().map(|name| metadata(&dir.path().join(name))).collect();
+
+        let hashed = DuplicateFinder::new(HashAlgorithm::SHA256).find_duplicates(files.clone());
+        assert_eq!(hashed.groups[0].verification, VerificationMethod::FullHash);
+
+        let verified = DuplicateFinder::new(HashAlgorithm::SHA256).with_verification(true).find_duplicates(files);
+        assert!(verified.is_complete());
+        assert_eq!(verified.groups.len(), 1);
+        assert_eq!(verified.groups[0].files.len(), 2);
+        assert_eq!(verified.groups[0].verification, VerificationMethod::ByteForByte);
+    }
+
+    #[test]
+    fn test_cancelled_run_is_marked_incomplete() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "identical content").unwrap();
+        fs::write(dir.path().join("b.txt"), "identical content").unwrap();
+        let files = ["a.txt", "b.txt"].iter().map(|name| metadata(&dir.path().join(name))).collect();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let report = DuplicateFinder::new(HashAlgorithm::SHA256)
+            .with_cancellation(cancel)
+            .find_duplicates(files);
+
+        assert!(report.groups.is_empty());
+        assert!(report.errors.is_empty());
+        assert_eq!(report.incomplete_stages, vec![ScanStage::Walk, ScanStage::Hashing]);
+    }
+}
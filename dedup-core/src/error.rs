@@ -0,0 +1,107 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+/// Why a file or directory could not be examined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ScanErrorKind {
+    PermissionDenied,
+    /// The entry was listed but disappeared before it could be read.
+    Vanished,
+    SymlinkLoop,
+    PathTooLong,
+    Io,
+}
+
+impl ScanErrorKind {
+    pub fn from_io(err: &io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::PermissionDenied => ScanErrorKind::PermissionDenied,
+            io::ErrorKind::NotFound => ScanErrorKind::Vanished,
+            io::ErrorKind::InvalidFilename => ScanErrorKind::PathTooLong,
+            _ => ScanErrorKind::Io,
+        }
+    }
+}
+
+impl fmt::Display for ScanErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScanErrorKind::PermissionDenied => "permission denied",
+            ScanErrorKind::Vanished => "vanished during scan",
+            ScanErrorKind::SymlinkLoop => "symlink loop",
+            ScanErrorKind::PathTooLong => "path too long",
+            ScanErrorKind::Io => "I/O error",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Pipeline stage at which a path was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum ScanStage {
+    Walk,
+    Metadata,
+    Hashing,
+    Verification,
+    Similarity,
+}
+
+impl fmt::Display for ScanStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ScanStage::Walk => "walk",
+            ScanStage::Metadata => "metadata",
+            ScanStage::Hashing => "hashing",
+            ScanStage::Verification => "verification",
+            ScanStage::Similarity => "similarity",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A path that was not examined, kept so reports can list it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanError {
+    pub path: PathBuf,
+    pub kind: ScanErrorKind,
+    pub stage: ScanStage,
+    pub message: String,
+}
+
+impl ScanError {
+    pub fn from_io(path: &Path, stage: ScanStage, err: &io::Error) -> Self {
+        ScanError {
+            path: path.to_path_buf(),
+            kind: ScanErrorKind::from_io(err),
+            stage,
+            message: err.to_string(),
+        }
+    }
+
+    pub fn from_walkdir(root: &Path, err: &walkdir::Error) -> Self {
+        let path = err.path().unwrap_or(root).to_path_buf();
+
+        let kind = if err.loop_ancestor().is_some() {
+            ScanErrorKind::SymlinkLoop
+        } else {
+            err.io_error().map(ScanErrorKind::from_io).unwrap_or(ScanErrorKind::Io)
+        };
+
+        ScanError {
+            path,
+            kind,
+            stage: ScanStage::Walk,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} during {} ({})", self.path.display(), self.kind, self.stage, self.message)
+    }
+}
+
+impl std::error::Error for ScanError {}
@@ -0,0 +1,154 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::Duration;
+use crate::models::ProgressUpdate;
+
+type Callback = Box<dyn Fn(ProgressUpdate) + Send + Sync + 'static>;
+
+/// Fans progress updates out to any number of callbacks and queue subscribers.
+///
+/// Subscribers get a bounded queue. Intermediate updates are dropped when a subscriber
+/// falls behind so the scan never waits on a slow UI; `Finished` is always delivered, in
+/// place of the oldest intermediate update if the queue is full.
+#[derive(Default)]
+pub struct EventBus {
+    callbacks: RwLock<Vec<Callback>>,
+    subscribers: Mutex<Vec<Arc<Queue>>>,
+}
+
+struct Queue {
+    updates: Mutex<VecDeque<ProgressUpdate>>,
+    ready: Condvar,
+    capacity: usize,
+}
+
+/// Receiving end of [`EventBus::subscribe`]; dropping it unsubscribes.
+pub struct Subscription {
+    queue: Arc<Queue>,
+}
+
+impl Subscription {
+    pub fn try_recv(&self) -> Option<ProgressUpdate> {
+        self.queue.updates.lock().unwrap().pop_front()
+    }
+
+    /// Waits up to `timeout` for the next update.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ProgressUpdate> {
+        let updates = self.queue.updates.lock().unwrap();
+        let (mut updates, _) = self.queue.ready.wait_timeout_while(updates, timeout, |u| u.is_empty()).unwrap();
+        updates.pop_front()
+    }
+
+    /// Every update queued so far, without waiting.
+    pub fn try_iter(&self) -> impl Iterator<Item = ProgressUpdate> + '_ {
+        std::iter::from_fn(|| self.try_recv())
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_event(&self, callback: Callback) {
+        self.callbacks.write().unwrap().push(callback);
+    }
+
+    pub fn subscribe(&self, capacity: usize) -> Subscription {
+        let queue = Arc::new(Queue { updates: Mutex::new(VecDeque::new()), ready: Condvar::new(), capacity: capacity.max(1) });
+        self.subscribers.lock().unwrap().push(Arc::clone(&queue));
+        Subscription { queue }
+    }
+
+    pub fn has_listeners(&self) -> bool {
+        !self.callbacks.read().unwrap().is_empty() || !self.subscribers.lock().unwrap().is_empty()
+    }
+
+    pub fn publish(&self, update: ProgressUpdate) {
+        for callback in self.callbacks.read().unwrap().iter() {
+            callback(update.clone());
+        }
+
+        let finished = matches!(update, ProgressUpdate::Finished(_));
+        self.subscribers.lock().unwrap().retain(|queue| {
+            // Подписка отброшена: остались только наши ссылки
+            if Arc::strong_count(queue) == 1 {
+                return false;
+            }
+            let mut updates = queue.updates.lock().unwrap();
+            if updates.len() >= queue.capacity {
+                if !finished {
+                    return true;
+                }
+                let oldest = updates.iter().position(|u| !matches!(u, ProgressUpdate::Finished(_))).unwrap_or(0);
+                updates.remove(oldest);
+            }
+            updates.push_back(update.clone());
+            queue.ready.notify_one();
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::models::ScanStats;
+
+    fn scanning(n: u64) -> ProgressUpdate {
+        ProgressUpdate::Scanning { current_path: PathBuf::from("x"), files_scanned: n }
+    }
+
+    #[test]
+    fn test_publish_reaches_every_subscriber() {
+        let bus = EventBus::new();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let counter_clone = counter.clone();
+        bus.on_event(Box::new(move |_| { counter_clone.fetch_add(1, Ordering::SeqCst); }));
+        let first = bus.subscribe(8);
+        let second = bus.subscribe(8);
+
+        bus.publish(scanning(1));
+        bus.publish(ProgressUpdate::Finished(ScanStats::new()));
+
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+        assert_eq!(first.try_iter().count(), 2);
+        assert_eq!(second.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn test_slow_subscriber_drops_intermediate_updates() {
+        let bus = EventBus::new();
+        let receiver = bus.subscribe(2);
+        for n in 0..10 {
+            bus.publish(scanning(n));
+        }
+        assert_eq!(receiver.try_iter().count(), 2);
+
+        drop(receiver);
+        bus.publish(scanning(11));
+        assert!(!bus.has_listeners());
+    }
+
+    #[test]
+    fn test_finished_reaches_a_subscriber_that_never_drains() {
+        let bus = Arc::new(EventBus::new());
+        let stalled = bus.subscribe(2);
+        let publisher = Arc::clone(&bus);
+        let worker = std::thread::spawn(move || {
+            for n in 0..10 {
+                publisher.publish(scanning(n));
+            }
+            publisher.publish(ProgressUpdate::Finished(ScanStats::new()));
+        });
+        worker.join().unwrap();
+
+        let updates: Vec<ProgressUpdate> = stalled.try_iter().collect();
+        assert_eq!(updates.len(), 2);
+        assert!(matches!(updates[0], ProgressUpdate::Scanning { files_scanned: 1, .. }));
+        assert!(matches!(updates[1], ProgressUpdate::Finished(_)));
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::Serialize;
+use crate::similarity::SimilarPair;
+
+/// File format of an exported similarity graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz.
+    Dot,
+    /// GraphML, as read by Gephi, yEd and Cytoscape.
+    GraphMl,
+    Json,
+}
+
+impl GraphFormat {
+    /// Format implied by the file extension, if it names one.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()?.to_str()?.to_ascii_lowercase().parse().ok()
+    }
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" | "gv" => Ok(GraphFormat::Dot),
+            "graphml" => Ok(GraphFormat::GraphMl),
+            "json" => Ok(GraphFormat::Json),
+            other => Err(format!("Unknown graph format '{}', expected dot, graphml or json", other)),
+        }
+    }
+}
+
+impl fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GraphFormat::Dot => "dot",
+            GraphFormat::GraphMl => "graphml",
+            GraphFormat::Json => "json",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    pub id: usize,
+    pub path: PathBuf,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub source: usize,
+    pub target: usize,
+    pub similarity: f64,
+    pub method: String,
+}
+
+/// Files as nodes and their pairwise similarities as weighted, undirected edges.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SimilarityGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl SimilarityGraph {
+    /// Graph of the pairs with at least `threshold` similarity; files only in weaker pairs are left out.
+    pub fn from_pairs(pairs: &[SimilarPair], threshold: f64) -> Self {
+        let kept: Vec<&SimilarPair> = pairs.iter().filter(|p| p.similarity >= threshold).collect();
+        let mut sizes: BTreeMap<&PathBuf, u64> = BTreeMap::new();
+        for pair in &kept {
+            sizes.insert(&pair.left, pair.left_size);
+            sizes.insert(&pair.right, pair.right_size);
+        }
+        let ids: BTreeMap<&PathBuf, usize> = sizes.keys().enumerate().map(|(id, path)| (*path, id)).collect();
+        let nodes = sizes
+            .iter()
+            .map(|(path, &size)| GraphNode { id: ids[path], path: (*path).clone(), size })
+            .collect();
+        let mut edges: Vec<GraphEdge> = kept
+            .iter()
+            .map(|pair| GraphEdge {
+                source: ids[&pair.left],
+                target: ids[&pair.right],
+                similarity: pair.similarity,
+                method: pair.method.clone(),
+            })
+            .collect();
+        edges.sort_by(|a, b| (a.source, a.target, &a.method).cmp(&(b.source, b.target, &b.method)));
+        SimilarityGraph { nodes, edges }
+    }
+
+    pub fn write<W: Write>(&self, format: GraphFormat, out: &mut W) -> io::Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(out),
+            GraphFormat::GraphMl => self.write_graphml(out),
+            GraphFormat::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)?;
+                writeln!(out)
+            }
+        }
+    }
+
+    fn write_dot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "graph similarity {{")?;
+        for node in &self.nodes {
+            let label = node.path.file_name().map_or_else(|| node.path.to_string_lossy(), |n| n.to_string_lossy());
+            writeln!(out, "  n{} [label=\"{}\", path=\"{}\", size={}];",
+                     node.id, dot_escape(&label), dot_escape(&node.path.to_string_lossy()), node.size)?;
+        }
+        for edge in &self.edges {
+            writeln!(out, "  n{} -- n{} [weight={:.4}, label=\"{:.0}%\", method=\"{}\"];",
+                     edge.source, edge.target, edge.similarity, edge.similarity * 100.0, dot_escape(&edge.method))?;
+        }
+        writeln!(out, "}}")
+    }
+
+    fn write_graphml<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(out, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(out, r#"  <key id="path" for="node" attr.name="path" attr.type="string"/>"#)?;
+        writeln!(out, r#"  <key id="size" for="node" attr.name="size" attr.type="long"/>"#)?;
+        writeln!(out, r#"  <key id="weight" for="edge" attr.name="similarity" attr.type="double"/>"#)?;
+        writeln!(out, r#"  <key id="method" for="edge" attr.name="method" attr.type="string"/>"#)?;
+        writeln!(out, r#"  <graph id="similarity" edgedefault="undirected">"#)?;
+        for node in &self.nodes {
+            writeln!(out, r#"    <node id="n{}">"#, node.id)?;
+            writeln!(out, r#"      <data key="path">{}</data>"#, xml_escape(&node.path.to_string_lossy()))?;
+            writeln!(out, r#"      <data key="size">{}</data>"#, node.size)?;
+            writeln!(out, r#"    </node>"#)?;
+        }
+        for (id, edge) in self.edges.iter().enumerate() {
+            writeln!(out, r#"    <edge id="e{}" source="n{}" target="n{}">"#, id, edge.source, edge.target)?;
+            writeln!(out, r#"      <data key="weight">{}</data>"#, edge.similarity)?;
+            writeln!(out, r#"      <data key="method">{}</data>"#, xml_escape(&edge.method))?;
+            writeln!(out, r#"    </edge>"#)?;
+        }
+        writeln!(out, "  </graph>")?;
+        writeln!(out, "</graphml>")
+    }
+}
+
+fn dot_escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::similarity::BLOCK_METHOD;
+
+    fn pair(left: &str, right: &str, similarity: f64) -> SimilarPair {
+        SimilarPair {
+            left: PathBuf::from(left),
+            right: PathBuf::from(right),
+            left_size: 10,
+            right_size: 20,
+            similarity,
+            positional: similarity,
+            method: BLOCK_METHOD.to_string(),
+            ranges: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_exports_pairs_above_threshold_in_every_format() {
+        let pairs = vec![pair("/d/a \"1\".txt", "/d/b&c.txt", 0.9), pair("/d/b&c.txt", "/d/z.txt", 0.2)];
+        let graph = SimilarityGraph::from_pairs(&pairs, 0.5);
+        assert_eq!(graph.nodes.len(), 2);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.nodes[1].size, 20);
+
+        let render = |format| {
+            let mut out = Vec::new();
+            graph.write(format, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let dot = render(GraphFormat::Dot);
+        assert!(dot.contains(r#"n0 [label="a \"1\".txt", path="/d/a \"1\".txt", size=10];"#), "{}", dot);
+        assert!(dot.contains(r#"n0 -- n1 [weight=0.9000, label="90%", method="block"];"#), "{}", dot);
+
+        let graphml = render(GraphFormat::GraphMl);
+        assert!(graphml.contains("<data key=\"path\">/d/b&amp;c.txt</data>"), "{}", graphml);
+        assert!(graphml.contains(r#"<edge id="e0" source="n0" target="n1">"#));
+
+        let json: serde_json::Value = serde_json::from_str(&render(GraphFormat::Json)).unwrap();
+        assert_eq!(json["edges"][0]["similarity"], 0.9);
+        assert_eq!(json["edges"][0]["method"], "block");
+        assert_eq!(json["nodes"][1]["path"], "/d/b&c.txt");
+
+        assert_eq!(GraphFormat::from_path(Path::new("out.GraphML")), Some(GraphFormat::GraphMl));
+        assert_eq!(GraphFormat::from_path(Path::new("out.txt")), None);
+    }
+}
@@ -0,0 +1,197 @@
+use std::io;
+use std::path::Path;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use xxhash_rust::xxh3::Xxh3;
+use crate::cancel::CancellationToken;
+use crate::models::{FileHash, HashAlgorithm};
+use crate::read_backend::{self, ReadOptions};
+
+enum Digester {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(Sha256),
+    Xxh3(Box<Xxh3>),
+    Md5(Md5),
+}
+
+impl Digester {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Digester::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::SHA256 => Digester::Sha256(Sha256::new()),
+            HashAlgorithm::XXH3 => Digester::Xxh3(Box::new(Xxh3::new())),
+            HashAlgorithm::MD5 => Digester::Md5(Md5::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Digester::Blake3(hasher) => { hasher.update(data); }
+            Digester::Sha256(hasher) => hasher.update(data),
+            Digester::Xxh3(hasher) => hasher.update(data),
+            Digester::Md5(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            Digester::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            Digester::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+            Digester::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+            Digester::Md5(hasher) => format!("{:x}", hasher.finalize()),
+        }
+    }
+}
+
+/// Which blocks the sampling pass reads: the first and last `block_size` bytes plus `blocks`
+/// evenly spaced blocks in between.
+///
+/// Files that share a header, such as videos from the same camera, usually differ somewhere
+/// in the samples, so most of them never need a full read. Matching samples prove nothing;
+/// survivors are always fully hashed before being reported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct SamplingOptions {
+    /// Smaller files skip sampling and go straight to the full hash.
+    pub min_file_size: u64,
+    pub block_size: u64,
+    pub blocks: u64,
+}
+
+impl Default for SamplingOptions {
+    fn default() -> Self {
+        SamplingOptions { min_file_size: 64 * 1024 * 1024, block_size: 64 * 1024, blocks: 16 }
+    }
+}
+
+impl SamplingOptions {
+    /// Sorted, non-overlapping `(offset, length)` ranges to read; the whole file when the
+    /// samples would cover it anyway.
+    pub fn ranges(&self, size: u64) -> Vec<(u64, u64)> {
+        let block = self.block_size.max(1);
+        if size <= block * (self.blocks + 2) {
+            return vec![(0, size)];
+        }
+        let last = size - block;
+        let mut ranges = Vec::with_capacity(self.blocks as usize + 2);
+        ranges.push((0, block));
+        ranges.extend((1..=self.blocks).map(|i| (i * last / (self.blocks + 1), block)));
+        ranges.push((last, block));
+        ranges
+    }
+
+    pub fn sampled_bytes(&self, size: u64) -> u64 {
+        self.ranges(size).iter().map(|(_, length)| length).sum()
+    }
+}
+
+/// Hashes the whole file.
+pub fn hash_file(path: &Path, algorithm: HashAlgorithm) -> io::Result<FileHash> {
+    let value = hash_path(path, algorithm, None, &ReadOptions::default(), None, None)?;
+    Ok(FileHash { algorithm, value, partial: false })
+}
+
+/// Hashes the file, or its first `limit` bytes, calling `on_read` with the size of every read.
+///
+/// Gives up with `ErrorKind::Interrupted` once `cancel` is set.
+pub fn hash_file_observed(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    limit: Option<u64>,
+    options: &ReadOptions,
+    cancel: &CancellationToken,
+    on_read: &dyn Fn(u64),
+) -> io::Result<FileHash> {
+    let value = hash_path(path, algorithm, limit, options, Some(cancel), Some(on_read))?;
+    Ok(FileHash { algorithm, value, partial: limit.is_some() })
+}
+
+/// Hashes at most the first `limit` bytes of the file.
+pub fn hash_file_prefix(path: &Path, algorithm: HashAlgorithm, limit: u64) -> io::Result<FileHash> {
+    let value = hash_path(path, algorithm, Some(limit), &ReadOptions::default(), None, None)?;
+    Ok(FileHash { algorithm, value, partial: true })
+}
+
+/// Hashes the blocks chosen by `sampling` for a file of `size` bytes; see [`SamplingOptions`].
+pub fn hash_file_sampled(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    size: u64,
+    sampling: &SamplingOptions,
+    options: &ReadOptions,
+    cancel: &CancellationToken,
+    on_read: &dyn Fn(u64),
+) -> io::Result<FileHash> {
+    let mut digester = Digester::new(algorithm);
+    read_backend::read_ranges(path, &sampling.ranges(size), options, Some(cancel), &mut |chunk| {
+        digester.update(chunk);
+        on_read(chunk.len() as u64);
+    })?;
+    Ok(FileHash { algorithm, value: digester.finish(), partial: true })
+}
+
+fn hash_path(
+    path: &Path,
+    algorithm: HashAlgorithm,
+    limit: Option<u64>,
+    options: &ReadOptions,
+    cancel: Option<&CancellationToken>,
+    on_read: Option<&dyn Fn(u64)>,
+) -> io::Result<String> {
+    let mut digester = Digester::new(algorithm);
+    read_backend::read_file(path, limit, options, cancel, &mut |chunk| {
+        digester.update(chunk);
+        if let Some(on_read) = on_read {
+            on_read(chunk.len() as u64);
+        }
+    })?;
+    Ok(digester.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_hash_file_known_digests() {
+        let mut file = NamedTempFile::new().unwrap();
+        write!(file, "abc").unwrap();
+        file.flush().unwrap();
+
+        let sha = hash_file(file.path(), HashAlgorithm::SHA256).unwrap();
+        assert_eq!(sha.value, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert!(!sha.partial);
+
+        let md5 = hash_file(file.path(), HashAlgorithm::MD5).unwrap();
+        assert_eq!(md5.value, "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[test]
+    fn test_sampling_ranges() {
+        let sampling = SamplingOptions { min_file_size: 0, block_size: 10, blocks: 2 };
+        assert_eq!(sampling.ranges(40), vec![(0, 40)]);
+        assert_eq!(sampling.ranges(100), vec![(0, 10), (30, 10), (60, 10), (90, 10)]);
+        assert_eq!(sampling.sampled_bytes(100), 40);
+    }
+
+    #[test]
+    fn test_hash_file_prefix() {
+        let mut file_1 = NamedTempFile::new().unwrap();
+        write!(file_1, "same header, tail one").unwrap();
+        let mut file_2 = NamedTempFile::new().unwrap();
+        write!(file_2, "same header, tail two").unwrap();
+
+        let prefix_1 = hash_file_prefix(file_1.path(), HashAlgorithm::Blake3, 11).unwrap();
+        let prefix_2 = hash_file_prefix(file_2.path(), HashAlgorithm::Blake3, 11).unwrap();
+        assert_eq!(prefix_1, prefix_2);
+        assert!(prefix_1.partial);
+
+        let full_1 = hash_file(file_1.path(), HashAlgorithm::Blake3).unwrap();
+        let full_2 = hash_file(file_2.path(), HashAlgorithm::Blake3).unwrap();
+        assert_ne!(full_1, full_2);
+    }
+}
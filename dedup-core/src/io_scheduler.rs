@@ -0,0 +1,293 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::config::{IoPolicy, ReadOrder, ScanConfig};
+use crate::models::FileMetadata;
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+/// Read scheduling for spinning disks.
+///
+/// Files under a root with an [`IoPolicy`] are split into one queue per device and sorted
+/// by physical location, so the disk head sweeps in one direction instead of seeking back
+/// and forth. Each queue is drained by a fixed number of readers, optionally throttled.
+/// When several policies cover one device, their files share its queue, and the smallest
+/// `readers_per_device` among them applies.
+pub struct IoScheduler {
+    policies: Vec<IoPolicy>,
+    throttles: Vec<Option<Throttle>>,
+}
+
+/// Files from one device, in read order, each with the throttle of its policy.
+pub(crate) struct ReadQueue<'a> {
+    pub files: Vec<(FileMetadata, Option<&'a Throttle>)>,
+    pub readers: usize,
+}
+
+impl IoScheduler {
+    pub fn new(policies: Vec<IoPolicy>) -> Self {
+        let throttles = policies
+            .iter()
+            .map(|p| p.max_mb_per_sec.map(|mb| Throttle::new(mb * BYTES_PER_MB)))
+            .collect();
+        IoScheduler { policies, throttles }
+    }
+
+    /// `None` when the config has no policies, so callers can keep their unscheduled path.
+    pub fn from_config(config: &ScanConfig) -> Option<Self> {
+        if config.io_policies.is_empty() {
+            None
+        } else {
+            Some(Self::new(config.io_policies.clone()))
+        }
+    }
+
+    /// Index of the policy with the longest root that contains `path`.
+    fn policy_for(&self, path: &Path) -> Option<usize> {
+        self.policies
+            .iter()
+            .enumerate()
+            .filter(|(_, policy)| path.starts_with(&policy.root))
+            .max_by_key(|(_, policy)| policy.root.components().count())
+            .map(|(index, _)| index)
+    }
+
+    /// Splits `files` into per-device queues in read order. Files outside every policy end
+    /// up in one unordered queue with a reader per worker thread.
+    pub(crate) fn plan(&self, files: Vec<FileMetadata>) -> Vec<ReadQueue<'_>> {
+        let mut buckets: BTreeMap<Option<u64>, Vec<PlannedFile>> = BTreeMap::new();
+        for file in files {
+            let policy = self.policy_for(&file.path);
+            let (device, location) = match policy {
+                Some(i) => {
+                    let (device, location) = locate(&file.path, self.policies[i].read_order);
+                    (Some(device), location)
+                }
+                None => (None, (true, 0, 0)),
+            };
+            buckets.entry(device).or_default().push((location, policy, file));
+        }
+
+        buckets
+            .into_values()
+            .map(|mut files| {
+                files.sort_by_key(|(location, _, _)| *location);
+                // Лимит читателей — на устройство, а не на политику: берём самый строгий
+                let readers = files
+                    .iter()
+                    .filter_map(|(_, policy, _)| self.policies[(*policy)?].readers_per_device)
+                    .min()
+                    .unwrap_or_else(rayon::current_num_threads)
+                    .max(1);
+                ReadQueue {
+                    files: files
+                        .into_iter()
+                        .map(|(_, policy, file)| (file, policy.and_then(|i| self.throttles[i].as_ref())))
+                        .collect(),
+                    readers,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Sort key and policy index (none for unmatched files) of a file waiting for its device queue.
+type PlannedFile = (Location, Option<usize>, FileMetadata);
+
+/// Sort key within a device: files with a known physical offset first, by offset, then by inode.
+type Location = (bool, u64, u64);
+
+/// Device id and sort key for `path`. Files whose metadata cannot be read sort last and
+/// fail later, when they are actually opened. The device is looked up for every order,
+/// since the reader limit is per device.
+#[cfg(unix)]
+fn locate(path: &Path, order: ReadOrder) -> (u64, Location) {
+    use std::os::unix::fs::MetadataExt;
+
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return (0, (true, u64::MAX, u64::MAX));
+    };
+    let location = match order {
+        ReadOrder::Any => (true, 0, 0),
+        ReadOrder::Inode => (true, 0, metadata.ino()),
+        ReadOrder::Extent => {
+            let offset = first_extent_offset(path);
+            (offset.is_none(), offset.unwrap_or(0), metadata.ino())
+        }
+    };
+    (metadata.dev(), location)
+}
+
+#[cfg(not(unix))]
+fn locate(_path: &Path, _order: ReadOrder) -> (u64, Location) {
+    (0, (true, 0, 0))
+}
+
+/// Physical byte offset of the file's first extent, via the FIEMAP ioctl.
+#[cfg(target_os = "linux")]
+fn first_extent_offset(path: &Path) -> Option<u64> {
+    use std::os::fd::AsRawFd;
+
+    // _IOWR('f', 11, struct fiemap)
+    const FS_IOC_FIEMAP: libc::c_ulong = 0xC020_660B;
+    const FIEMAP_FLAG_SYNC: u32 = 0x1;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct FiemapExtent {
+        fe_logical: u64,
+        fe_physical: u64,
+        fe_length: u64,
+        fe_reserved64: [u64; 2],
+        fe_flags: u32,
+        fe_reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Fiemap {
+        fm_start: u64,
+        fm_length: u64,
+        fm_flags: u32,
+        fm_mapped_extents: u32,
+        fm_extent_count: u32,
+        fm_reserved: u32,
+        fm_extents: [FiemapExtent; 1],
+    }
+
+    let file = std::fs::File::open(path).ok()?;
+    let mut map = Fiemap { fm_length: u64::MAX, fm_flags: FIEMAP_FLAG_SYNC, fm_extent_count: 1, ..Default::default() };
+    // SAFETY: `map` is a properly laid out `struct fiemap` with room for the one extent
+    // requested in `fm_extent_count`, and it outlives the call.
+    let result = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut map as *mut Fiemap) };
+    if result != 0 || map.fm_mapped_extents == 0 {
+        return None;
+    }
+    Some(map.fm_extents[0].fe_physical)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn first_extent_offset(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Caps the combined read rate of every reader sharing it.
+///
+/// Readers report what they read and sleep until the total is back under the rate. Credit
+/// does not pile up while nobody reads, so a pause is not followed by a burst.
+pub(crate) struct Throttle {
+    bytes_per_sec: f64,
+    window: Mutex<Option<(Instant, u64)>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(bytes_per_sec: f64) -> Self {
+        Throttle { bytes_per_sec, window: Mutex::new(None) }
+    }
+
+    pub(crate) fn consume(&self, bytes: u64) {
+        if self.bytes_per_sec <= 0.0 {
+            return;
+        }
+        let now = Instant::now();
+        let due = {
+            let mut window = self.window.lock().unwrap();
+            let (start, total) = window.get_or_insert((now, 0));
+            // Idle for a while: start a fresh window instead of granting a burst.
+            if now > *start + self.duration_for(*total) + Duration::from_secs(1) {
+                *start = now;
+                *total = 0;
+            }
+            *total += bytes;
+            *start + self.duration_for(*total)
+        };
+        if due > now {
+            thread::sleep(due - now);
+        }
+    }
+
+    fn duration_for(&self, bytes: u64) -> Duration {
+        Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::tempdir;
+
+    fn file(path: PathBuf) -> FileMetadata {
+        FileMetadata { path, size: 1, hash: None, modified: None, created: None }
+    }
+
+    #[test]
+    fn test_plan_orders_policy_roots_and_leaves_the_rest_alone() {
+        let dir = tempdir().unwrap();
+        let nas = dir.path().join("nas");
+        fs::create_dir(&nas).unwrap();
+        let mut files = Vec::new();
+        for i in 0..10 {
+            let path = nas.join(format!("{i}.bin"));
+            fs::write(&path, "x").unwrap();
+            files.push(file(path));
+        }
+        files.reverse();
+        files.push(file(dir.path().join("elsewhere.bin")));
+
+        let scheduler = IoScheduler::new(vec![IoPolicy {
+            root: nas.clone(),
+            read_order: ReadOrder::Inode,
+            readers_per_device: Some(1),
+            max_mb_per_sec: None,
+        }]);
+        let queues = scheduler.plan(files);
+        assert_eq!(queues.len(), 2);
+
+        let other = queues.iter().find(|q| !q.files[0].0.path.starts_with(&nas)).unwrap();
+        assert_eq!(other.files.len(), 1);
+        assert_eq!(other.readers, rayon::current_num_threads());
+
+        let planned = queues.iter().find(|q| q.files[0].0.path.starts_with(&nas)).unwrap();
+        assert_eq!(planned.readers, 1);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let inodes: Vec<u64> = planned.files.iter().map(|(f, _)| fs::metadata(&f.path).unwrap().ino()).collect();
+            assert!(inodes.is_sorted());
+        }
+    }
+
+    #[test]
+    fn test_policies_on_one_device_share_its_reader_limit() {
+        let dir = tempdir().unwrap();
+        let policy = |name: &str, readers, rate| {
+            let root = dir.path().join(name);
+            fs::create_dir(&root).unwrap();
+            fs::write(root.join("file.bin"), "x").unwrap();
+            IoPolicy { root, read_order: ReadOrder::Any, readers_per_device: Some(readers), max_mb_per_sec: rate }
+        };
+        let policies = vec![policy("photos", 1, Some(10.0)), policy("music", 3, None)];
+        let files = policies.iter().map(|p| file(p.root.join("file.bin"))).collect();
+
+        let scheduler = IoScheduler::new(policies);
+        let queues = scheduler.plan(files);
+        assert_eq!(queues.len(), 1);
+        assert_eq!(queues[0].readers, 1);
+        let throttled: Vec<bool> = queues[0].files.iter().map(|(f, throttle)| f.path.starts_with(dir.path().join("photos")) == throttle.is_some()).collect();
+        assert_eq!(throttled, vec![true, true]);
+    }
+
+    #[test]
+    fn test_throttle_limits_rate() {
+        let throttle = Throttle::new(1_000_000.0);
+        let start = Instant::now();
+        for _ in 0..5 {
+            throttle.consume(20_000);
+        }
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+}
@@ -1,3 +1,26 @@
 pub mod scanner;
 pub mod models;
 pub mod config;
+pub mod config_loader;
+pub mod validation;
+pub mod error;
+pub mod hasher;
+pub mod duplicates;
+pub mod events;
+pub mod walker;
+pub mod cancel;
+pub mod checkpoint;
+pub mod progress;
+pub mod io_scheduler;
+pub mod read_backend;
+pub mod verify;
+pub mod reference;
+pub mod compare;
+pub mod directories;
+pub mod similarity;
+pub mod clustering;
+pub mod graph;
+pub mod versions;
+pub mod diff;
+pub mod delta;
+pub mod plugins;
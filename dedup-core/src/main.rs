@@ -1,10 +1,30 @@
-use std::{process};
-use std::path::{Path};
+use std::{env, fs, io, process};
+use std::io::{IsTerminal, Write};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use dedup_core::cancel::CancellationToken;
+use dedup_core::checkpoint::ResumableScan;
+use dedup_core::clustering::{self, ClusterMethod};
+use dedup_core::compare::{TreeComparer, TreeComparison};
 use dedup_core::config;
-use dedup_core::models::ProgressUpdate;
+use dedup_core::config_loader::ConfigLoader;
+use dedup_core::delta::{self, DeltaOptions};
+use dedup_core::diff;
+use dedup_core::directories::{self, SimilarityOptions};
+use dedup_core::duplicates::DuplicateFinder;
+use dedup_core::error::{ScanError, ScanStage};
+use dedup_core::graph::{GraphFormat, SimilarityGraph};
+use dedup_core::io_scheduler::IoScheduler;
+use dedup_core::models::{summarize_errors, ProgressUpdate};
+use dedup_core::plugins::PluginConfig;
+use dedup_core::reference::ReferenceSet;
 use dedup_core::scanner::{ScanConfig, Scanner};
+use dedup_core::similarity::{BlockOptions, SimilarityFinder};
+use dedup_core::versions;
 
-const CONFIG_FILE: &str = "/mnt/new_disk/Dev/Rust/file_deduplicator/conf.json";
+const STREAM_BUFFER: usize = 4096;
+const USAGE_TOP: usize = 20;
 
 fn main() {
     if let Err(e) = run_program() {
@@ -13,38 +33,492 @@ fn main() {
     }
 }
 
+/// `--checkpoint <file>` saves progress periodically; `--resume` continues from that file.
+/// `--compare <left> <right>` compares two trees by content instead of finding duplicates.
+/// `--similar-dirs <ratio>` also lists directory pairs sharing at least that share of content.
+/// `--similar-files <ratio>` groups files sharing at least that share of their blocks, matched
+/// with the settings of the `piece_wise` plugin when one is configured;
+/// `--cluster <connected|complete|medoid>` picks how the groups are formed, and
+/// `--graph <file.dot|file.graphml|file.json>` exports the similar pairs as a weighted graph;
+/// `--versions` shows each group as a likely version chain.
+/// `--diff <left> <right>` shows why two files are similar; `--html <file>` also saves it as a page.
+/// `--archive-similar <dir>` keeps each group's representative and replaces the other files with
+/// patches under `<dir>`, one archive per representative that later runs add to;
+/// `--restore <manifest>` rebuilds them.
+/// `--usage` lists the directories with the most reclaimable space. These reports need the
+/// duplicates among the roots, so they are refused when `reference_paths` is set.
+/// `--config <file>`, `--profile <name>`, `--set <key>=<value>` and `--root <dir>` (repeatable)
+/// override the layered configuration; `--print-config` shows the result and where each
+/// value came from. `--print-schema` prints the JSON Schema for config files.
+struct Args {
+    checkpoint: Option<PathBuf>,
+    resume: bool,
+    compare: Option<(PathBuf, PathBuf)>,
+    diff: Option<(PathBuf, PathBuf)>,
+    html: Option<PathBuf>,
+    archive_similar: Option<PathBuf>,
+    restore: Option<PathBuf>,
+    similar_dirs: Option<f64>,
+    similar_files: Option<f64>,
+    cluster: ClusterMethod,
+    graph: Option<(PathBuf, GraphFormat)>,
+    versions: bool,
+    usage: bool,
+    config: Option<PathBuf>,
+    profile: Option<String>,
+    overrides: Vec<(String, String)>,
+    roots: Vec<PathBuf>,
+    print_config: bool,
+    print_schema: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        checkpoint: None,
+        resume: false,
+        compare: None,
+        diff: None,
+        html: None,
+        archive_similar: None,
+        restore: None,
+        similar_dirs: None,
+        similar_files: None,
+        cluster: ClusterMethod::default(),
+        graph: None,
+        versions: false,
+        usage: false,
+        config: None,
+        profile: None,
+        overrides: Vec::new(),
+        roots: Vec::new(),
+        print_config: false,
+        print_schema: false,
+    };
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--checkpoint" => {
+                let path = iter.next().ok_or("--checkpoint requires a file path")?;
+                args.checkpoint = Some(PathBuf::from(path));
+            }
+            "--resume" | "resume" => args.resume = true,
+            "--usage" => args.usage = true,
+            "--versions" => args.versions = true,
+            "--print-config" => args.print_config = true,
+            "--print-schema" => args.print_schema = true,
+            "--config" => {
+                let path = iter.next().ok_or("--config requires a file path")?;
+                args.config = Some(PathBuf::from(path));
+            }
+            "--profile" => args.profile = Some(iter.next().ok_or("--profile requires a profile name")?),
+            "--root" => {
+                let path = iter.next().ok_or("--root requires a directory")?;
+                args.roots.push(PathBuf::from(path));
+            }
+            "--set" => {
+                let setting = iter.next().ok_or("--set requires <key>=<value>")?;
+                let (key, value) = setting.split_once('=').ok_or_else(|| format!("Invalid setting '{}', expected <key>=<value>", setting))?;
+                args.overrides.push((key.trim().to_string(), value.to_string()));
+            }
+            "--compare" => {
+                let left = iter.next().ok_or("--compare requires two directories")?;
+                let right = iter.next().ok_or("--compare requires two directories")?;
+                args.compare = Some((PathBuf::from(left), PathBuf::from(right)));
+            }
+            "--diff" => {
+                let left = iter.next().ok_or("--diff requires two files")?;
+                let right = iter.next().ok_or("--diff requires two files")?;
+                args.diff = Some((PathBuf::from(left), PathBuf::from(right)));
+            }
+            "--archive-similar" => {
+                args.archive_similar = Some(PathBuf::from(iter.next().ok_or("--archive-similar requires a directory")?));
+            }
+            "--restore" => args.restore = Some(PathBuf::from(iter.next().ok_or("--restore requires a manifest file")?)),
+            "--html" => args.html = Some(PathBuf::from(iter.next().ok_or("--html requires a file path")?)),
+            "--similar-dirs" => {
+                let ratio = iter.next().ok_or("--similar-dirs requires a ratio between 0 and 1")?;
+                let ratio: f64 = ratio.parse().map_err(|_| format!("Invalid ratio '{}' for --similar-dirs", ratio))?;
+                if !(0.0..=1.0).contains(&ratio) {
+                    return Err(format!("Ratio for --similar-dirs must be between 0 and 1, got {}", ratio));
+                }
+                args.similar_dirs = Some(ratio);
+            }
+            "--similar-files" => {
+                let ratio = iter.next().ok_or("--similar-files requires a ratio between 0 and 1")?;
+                let ratio: f64 = ratio.parse().map_err(|_| format!("Invalid ratio '{}' for --similar-files", ratio))?;
+                if !(0.0..=1.0).contains(&ratio) {
+                    return Err(format!("Ratio for --similar-files must be between 0 and 1, got {}", ratio));
+                }
+                args.similar_files = Some(ratio);
+            }
+            "--cluster" => {
+                let method = iter.next().ok_or("--cluster requires connected, complete or medoid")?;
+                args.cluster = method.parse()?;
+            }
+            "--graph" => {
+                let path = PathBuf::from(iter.next().ok_or("--graph requires a file path")?);
+                let format = GraphFormat::from_path(&path)
+                    .ok_or_else(|| format!("Cannot tell the graph format of '{}', use a .dot, .graphml or .json file", path.display()))?;
+                args.graph = Some((path, format));
+            }
+            other => return Err(format!("Unknown argument '{}'", other)),
+        }
+    }
+    if args.resume && args.checkpoint.is_none() {
+        return Err("--resume requires --checkpoint <file>".into());
+    }
+    if args.graph.is_some() && args.similar_files.is_none() {
+        return Err("--graph requires --similar-files <ratio>".into());
+    }
+    if args.html.is_some() && args.diff.is_none() {
+        return Err("--html requires --diff <left> <right>".into());
+    }
+    if args.archive_similar.is_some() && args.similar_files.is_none() {
+        return Err("--archive-similar requires --similar-files <ratio>".into());
+    }
+    if args.versions && args.similar_files.is_none() {
+        return Err("--versions requires --similar-files <ratio>".into());
+    }
+    Ok(args)
+}
+
 fn run_program() -> Result<(), Box<dyn std::error::Error>> {
-    let config_file_path = Path::new(CONFIG_FILE);
-    println!("Using config file '{}'", config_file_path.display());
-    let config_file = config::read_config_file(config_file_path)?;
-    println!("Deduplicator run on directory [{:?}]", config_file.root_paths);
-    println!("Config file loaded successfully");
+    let args = parse_args()?;
+    if args.print_schema {
+        println!("{}", serde_json::to_string_pretty(&config::json_schema())?);
+        return Ok(());
+    }
+    if let Some(manifest) = &args.restore {
+        for path in delta::restore(manifest)? {
+            println!("Восстановлен: {}", path.display());
+        }
+        return Ok(());
+    }
+    let mut loader = ConfigLoader::new();
+    if let Some(path) = &args.config {
+        loader = loader.with_file(path.clone());
+    }
+    if let Some(profile) = &args.profile {
+        loader = loader.with_profile(profile);
+    }
+    for (key, value) in &args.overrides {
+        loader = loader.with_override(key, value);
+    }
+    if !args.roots.is_empty() {
+        loader = loader.with_override("root_paths", &serde_json::to_string(&args.roots)?);
+    }
+    let loaded = loader.load()?;
+    if args.print_config {
+        match &loaded.profile {
+            Some(profile) => println!("# profile: {}", profile),
+            None if !loaded.profiles.is_empty() => println!("# no profile selected; defined: {}", loaded.profiles.join(", ")),
+            None => {}
+        }
+        print!("{}", loaded.describe());
+        return Ok(());
+    }
+    let piece_wise = loaded.config.plugins.iter().find_map(|plugin| match plugin {
+        PluginConfig::PieceWise(settings) => Some(settings.block_options()),
+        _ => None,
+    });
+    if let Some((left, right)) = &args.diff {
+        // Те же блоки и та же оценка, что и у --similar-files
+        let pair = diff::diff_files(left, right, &piece_wise.unwrap_or_default())?;
+        print!("{}", pair.render_terminal(io::stdout().is_terminal()));
+        if let Some(path) = &args.html {
+            fs::write(path, pair.render_html())?;
+            println!("Сравнение сохранено: {}", path.display());
+        }
+        return Ok(());
+    }
+    // С эталонными папками выводятся только совпадения с ними, остальные отчёты не строятся
+    if !loaded.config.reference_paths.is_empty() {
+        let flags = [("--similar-files", args.similar_files.is_some()), ("--similar-dirs", args.similar_dirs.is_some()), ("--usage", args.usage)];
+        if let Some((flag, _)) = flags.iter().find(|(_, set)| *set) {
+            return Err(format!("{} cannot be used with reference_paths; reference mode only lists files already in the references", flag).into());
+        }
+    }
+    for file in &loaded.files {
+        println!("Using {}", file);
+    }
+    for warning in &loaded.warnings {
+        println!("{}", warning);
+    }
+    if let Some(profile) = &loaded.profile {
+        println!("Profile '{}'", profile);
+    }
+    println!("Deduplicator run on directory [{:?}]", loaded.config.root_paths);
+    let algorithm = loaded.config.algorithm;
 
-    let scan_config = ScanConfig::build(config_file)?;
+    let scan_config = ScanConfig::build(loaded.config)?;
+    let references = Arc::new(ReferenceSet::new(scan_config.reference_paths.clone()));
 
     // Создаем callback с поддержкой Send + Sync
     let callback: Option<Box<dyn Fn(ProgressUpdate) + Send + Sync>> = Some(Box::new(|update| {
         match update {
-            ProgressUpdate::Scanning { current_path, files_scanned } => {
-                if files_scanned % 100 == 0 {
-                    println!("Найдено файлов: {}", files_scanned);
-                }
+            ProgressUpdate::Scanning { files_scanned, .. } if files_scanned % 100 == 0 => {
+                println!("Найдено файлов: {}", files_scanned);
+            }
+            ProgressUpdate::Hashing { partial, progress, .. } => {
+                let eta = progress.eta.map_or_else(|| "?".to_string(), |eta| format!("{}s", eta.as_secs()));
+                println!("{}: {}/{} файлов, {}/{} MB, {:.1} MB/s, осталось {}",
+                         if partial { "Хеширование начала" } else { "Хеширование" },
+                         progress.files_done,
+                         progress.total_files,
+                         progress.bytes_done / 1024 / 1024,
+                         progress.total_bytes / 1024 / 1024,
+                         progress.bytes_per_second / 1024.0 / 1024.0,
+                         eta);
+            }
+            ProgressUpdate::Processing { stage, progress, .. } => {
+                println!("{}: {}/{} файлов", stage, progress.files_done, progress.total_files);
             }
             ProgressUpdate::Finished(stats) => {
-                println!("Завершено! Файлов: {}, Размер: {} MB, Время: {:?}",
+                println!("Завершено! Файлов: {}, Размер: {} MB, Ошибок: {}, Время: {:?}",
                          stats.files_scanned,
                          stats.total_size / 1024 / 1024,
+                         stats.errors,
                          stats.elapsed());
             }
             _ => {}
         }
     }));
 
-    let scanner = Scanner::new(scan_config, callback);
+    let cancel = install_interrupt_handler()?;
+    let walk_roots: Vec<PathBuf> = scan_config.walk_roots().cloned().collect();
+    let collapse_directories = scan_config.collapse_directories;
+    let checkpointing = args.checkpoint.is_some();
+    // Полный список файлов нужен только отчётам по папкам и похожим файлам
+    let collect_files = args.similar_dirs.is_some() || args.usage || args.similar_files.is_some();
+
+    if let Some((left, right)) = args.compare {
+        let comparer = TreeComparer::new(scan_config, algorithm).with_cancellation(cancel);
+        if let Some(callback) = callback {
+            comparer.events().on_event(callback);
+        }
+        let comparison = comparer.compare(&left, &right)?;
+        print_comparison(&comparison);
+        return Ok(());
+    }
+
+    let (report, files) = if let Some(checkpoint) = args.checkpoint {
+        println!("Checkpoint file '{}'{}", checkpoint.display(), if args.resume { ", resuming" } else { "" });
+        let run = ResumableScan::new(scan_config, algorithm, checkpoint)
+            .with_cancellation(cancel.clone());
+        if let Some(callback) = callback {
+            run.events().on_event(callback);
+        }
+        run.run_collecting(args.resume)?
+    } else {
+        let scheduler = IoScheduler::from_config(&scan_config);
+        let read_options = scan_config.read.clone();
+        let sampling = scan_config.sampling.clone();
+        let verify_bytes = scan_config.verify_bytes;
+        let finder_references = Arc::clone(&references);
+        let scanner = Scanner::new(scan_config, callback).with_cancellation(cancel.clone());
+        let scanned = Mutex::new(Vec::new());
+        let stream = scanner.stream(STREAM_BUFFER)?.inspect(|item| {
+            if let Ok(file) = item
+                && collect_files
+            {
+                scanned.lock().unwrap().push(file.clone());
+            }
+        });
+        let mut finder = DuplicateFinder::new(algorithm)
+            .with_cancellation(cancel.clone())
+            .with_events(Arc::clone(scanner.events()))
+            .with_read_options(read_options)
+            .with_sampling(sampling)
+            .with_verification(verify_bytes)
+            .with_reference_set(finder_references);
+        if let Some(scheduler) = scheduler {
+            finder = finder.with_io_scheduler(Arc::new(scheduler));
+        }
+        let report = finder.find_duplicates_streaming(stream);
+        (report, scanned.into_inner().unwrap())
+    };
+    let mut errors = report.errors;
+    let mut incomplete_stages = report.incomplete_stages;
 
+    if references.is_empty() {
+        let directory_groups = directories::find_duplicate_directories(&report.groups, &walk_roots);
+        for group in &directory_groups {
+            println!("Одинаковые папки ({} files, {} bytes each)", group.file_count, group.size);
+            for directory in &group.directories {
+                println!("  {}", directory.display());
+            }
+        }
+        if let Some(min_overlap) = args.similar_dirs {
+            let options = SimilarityOptions { min_overlap, ..Default::default() };
+            for pair in directories::find_similar_directories(&files, &report.groups, &walk_roots, &options) {
+                println!("Похожие папки ({:.0}%, общих {} bytes)", pair.overlap * 100.0, pair.shared_bytes);
+                println!("  {} ({} bytes, только здесь: {})", pair.left.display(), pair.left_bytes, pair.only_left.len());
+                println!("  {} ({} bytes, только здесь: {})", pair.right.display(), pair.right_bytes, pair.only_right.len());
+                for file in &pair.only_right {
+                    println!("    + {}", file.display());
+                }
+            }
+        }
+        if args.usage {
+            let mut usage = directories::redundancy_rollup(&files, &report.groups, &walk_roots);
+            usage.sort_by(|a, b| b.reclaimable_bytes.cmp(&a.reclaimable_bytes).then_with(|| a.path.cmp(&b.path)));
+            println!("Папки, где можно освободить больше всего места:");
+            for entry in usage.iter().take(USAGE_TOP).filter(|u| u.reclaimable_bytes > 0) {
+                println!("  {} MB освободится, {} MB дубликатов, {} MB уникальных: {}",
+                         entry.reclaimable_bytes / 1024 / 1024,
+                         entry.duplicated_bytes / 1024 / 1024,
+                         entry.unique_bytes / 1024 / 1024,
+                         entry.path.display());
+            }
+        }
+        // Прерванный прогон не даёт полного списка похожих файлов, а архивация удаляет оригиналы,
+        // поэтому после Ctrl-C ни отчёты о похожих файлах, ни архив не строятся
+        let skipped_archive = if args.archive_similar.is_some() { ", архивирование пропущено" } else { "" };
+        if let Some(min_similarity) = args.similar_files {
+            if !incomplete_stages.is_empty() || cancel.is_cancelled() {
+                println!("Похожие файлы не искались: прогон прерван{}", skipped_archive);
+            } else {
+                // Из точных дубликатов сравниваем только первый файл группы
+                let copies: HashSet<&PathBuf> = report.groups.iter().flat_map(|g| g.files.iter().skip(1).map(|f| &f.path)).collect();
+                let candidates: Vec<PathBuf> = files.iter().map(|f| &f.path).filter(|p| !copies.contains(p)).cloned().collect();
+                let options = BlockOptions { min_similarity, ..piece_wise.unwrap_or_default() };
+                let similar = SimilarityFinder::new(options.clone()).with_cancellation(cancel.clone()).find(&candidates);
+                errors.extend(similar.errors);
+                if !similar.incomplete_stages.is_empty() {
+                    incomplete_stages.extend(similar.incomplete_stages);
+                    println!("Похожие файлы не показаны: поиск прерван{}", skipped_archive);
+                } else {
+                    let pairs = similar.pairs;
+                    if let Some((path, format)) = &args.graph {
+                        let graph = SimilarityGraph::from_pairs(&pairs, min_similarity);
+                        let mut out = io::BufWriter::new(fs::File::create(path)?);
+                        graph.write(*format, &mut out)?;
+                        out.flush()?;
+                        println!("Граф сходства ({}, {} файлов, {} связей): {}", format, graph.nodes.len(), graph.edges.len(), path.display());
+                    }
+                    for cluster in &clustering::cluster_pairs(&pairs, min_similarity, args.cluster) {
+                        println!("Похожие файлы ({}, сходство не ниже {:.0}%, в среднем {:.0}%)",
+                                 cluster.files.len(),
+                                 cluster.min_similarity * 100.0,
+                                 cluster.avg_similarity * 100.0);
+                        if args.versions {
+                            let members: Vec<_> = files.iter().filter(|f| cluster.files.contains(&f.path)).cloned().collect();
+                            match versions::build_chain(&members, options.block_size) {
+                                Ok(chain) => print_version_chain(&chain),
+                                Err(error) => errors.push(error),
+                            }
+                        } else {
+                            for file in &cluster.files {
+                                let marker = if *file == cluster.representative { "*" } else { " " };
+                                println!(" {}{}", marker, file.display());
+                            }
+                        }
+                        if let Some(archive) = &args.archive_similar {
+                            if cancel.is_cancelled() {
+                                println!("  архивирование прервано, остальные группы не тронуты");
+                                incomplete_stages.push(ScanStage::Similarity);
+                                break;
+                            }
+                            let (manifest_path, manifest) = delta::archive_cluster(cluster, archive, &DeltaOptions::default())?;
+                            println!("  оставлен {}, в архиве патчей: {}, освобождено {} bytes, восстановление: --restore {}",
+                                     manifest.representative.display(), manifest.files.len(), manifest.saved_bytes(), manifest_path.display());
+                        }
+                    }
+                }
+            }
+        }
+        let groups = if collapse_directories {
+            directories::collapse_file_groups(report.groups, &directory_groups)
+        } else {
+            report.groups
+        };
+        for group in &groups {
+            println!("{} ({} files, {} bytes, {})", group.hash.value, group.files.len(), group.total_size, group.verification);
+            for file in &group.files {
+                println!("  {}", file.path.display());
+            }
+        }
+    } else {
+        for found in references.matches(&report.groups) {
+            println!("Уже в архиве: {} ({} bytes, {})", found.references[0].path.display(), found.size, found.verification);
+            for file in &found.targets {
+                println!("  {}", file.path.display());
+            }
+        }
+    }
 
-    let files = scanner.scan()?;
+    print_errors(&errors);
 
-    let files = scanner.scan_parallel()?;
+    if !incomplete_stages.is_empty() {
+        let stages: Vec<String> = incomplete_stages.iter().map(|s| s.to_string()).collect();
+        println!("Прервано: результат неполный, не завершены этапы: {}", stages.join(", "));
+        if checkpointing {
+            println!("Запустите с --resume, чтобы продолжить с контрольной точки");
+        }
+    }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// First Ctrl-C cancels the run and lets it report what it has; a second one exits at once.
+fn install_interrupt_handler() -> Result<CancellationToken, ctrlc::Error> {
+    let cancel = CancellationToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            process::exit(130);
+        }
+        println!("Остановка... Нажмите Ctrl-C ещё раз для немедленного выхода");
+        handler_cancel.cancel();
+    })?;
+    Ok(cancel)
+}
+
+fn print_comparison(comparison: &TreeComparison) {
+    for path in &comparison.missing {
+        println!("Отсутствует: {}", path.display());
+    }
+    for moved in &comparison.moved {
+        println!("Перемещён: {} -> {}", moved.from.display(), moved.to.display());
+    }
+    for path in &comparison.changed {
+        println!("Изменён: {}", path.display());
+    }
+    for path in &comparison.added {
+        println!("Новый: {}", path.display());
+    }
+    println!("Без изменений: {}", comparison.unchanged);
+    print_errors(&comparison.errors);
+
+    if !comparison.is_complete() {
+        println!("Прервано: результат сравнения неполный");
+    } else if comparison.is_in_sync() {
+        println!("Копия совпадает с оригиналом");
+    }
+}
+
+fn print_version_chain(chain: &versions::VersionChain) {
+    println!("  {}", chain.files[0].display());
+    for step in &chain.steps {
+        println!("  -> {} ({:.0}%: сохранено {} bytes, добавлено {}, удалено {}, изменено {})",
+                 step.to.display(),
+                 step.similarity * 100.0,
+                 step.kept_bytes,
+                 step.added_bytes,
+                 step.removed_bytes,
+                 step.changed_bytes);
+    }
+}
+
+fn print_errors(errors: &[ScanError]) {
+    if errors.is_empty() {
+        return;
+    }
+
+    println!("Не проверено файлов: {}", errors.len());
+    for (kind, count) in summarize_errors(errors) {
+        println!("  {}: {}", kind, count);
+    }
+    for error in errors {
+        println!("  {}", error);
+    }
+}
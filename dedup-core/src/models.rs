@@ -1,6 +1,9 @@
+use std::collections::BTreeMap;
 use std::path::{PathBuf};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
+use crate::error::{ScanError, ScanErrorKind, ScanStage};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileMetadata {
@@ -18,7 +21,7 @@ pub struct FileHash {
     pub partial: bool,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, Hash, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema, Eq, Hash, PartialEq)]
 pub enum HashAlgorithm {
     Blake3,
     SHA256,
@@ -31,20 +34,47 @@ pub struct DuplicateGroup {
     pub files: Vec<FileMetadata>,
     pub total_size: u64,
     pub hash: FileHash,
+    pub verification: VerificationMethod,
+}
+
+/// What established that the files in a group are identical.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VerificationMethod {
+    /// Equal size and full-content hash.
+    FullHash,
+    /// Compared byte for byte after hashing.
+    ByteForByte,
+}
+
+impl std::fmt::Display for VerificationMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            VerificationMethod::FullHash => "full hash",
+            VerificationMethod::ByteForByte => "byte-for-byte",
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ScanStats {
     pub files_scanned: u64,
     pub total_size: u64,
+    pub errors: u64,
     pub start_time: std::time::Instant,
 }
 
+impl Default for ScanStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ScanStats {
     pub fn new() -> Self {
         Self {
             files_scanned: 0,
             total_size: 0,
+            errors: 0,
             start_time: std::time::Instant::now(),
         }
     }
@@ -54,21 +84,63 @@ impl ScanStats {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct ScanResult {
+    pub files: Vec<FileMetadata>,
+    pub errors: Vec<ScanError>,
+    pub stats: ScanStats,
+    /// Stages that were cancelled before finishing; empty for a full result.
+    pub incomplete_stages: Vec<ScanStage>,
+}
+
+impl ScanResult {
+    pub fn is_complete(&self) -> bool {
+        self.incomplete_stages.is_empty()
+    }
+
+    pub fn error_summary(&self) -> BTreeMap<ScanErrorKind, usize> {
+        summarize_errors(&self.errors)
+    }
+}
+
+pub fn summarize_errors(errors: &[ScanError]) -> BTreeMap<ScanErrorKind, usize> {
+    let mut summary = BTreeMap::new();
+    for error in errors {
+        *summary.entry(error.kind).or_insert(0) += 1;
+    }
+    summary
+}
+
 #[derive(Debug, Clone)]
 pub enum ProgressUpdate {
     Scanning {
         current_path: PathBuf,
         files_scanned: u64,
     },
+    /// A non-hashing detection stage, named by `stage`.
     Processing {
+        stage: String,
         current_file: PathBuf,
-        files_processed: u64,
-        total_files: u64,
+        progress: StageProgress,
     },
+    /// Prefix (`partial`) or full-content hashing.
     Hashing {
+        partial: bool,
         current_file: PathBuf,
-        bytes_hashed: u64,
-        total_bytes: u64,
+        progress: StageProgress,
     },
     Finished(ScanStats),
-}
\ No newline at end of file
+}
+
+/// How far a detection stage has got. Totals can grow while the walk is still feeding the stage.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StageProgress {
+    pub files_done: u64,
+    pub total_files: u64,
+    pub bytes_done: u64,
+    pub total_bytes: u64,
+    /// Recent read rate, smoothed over the last few updates.
+    pub bytes_per_second: f64,
+    /// `None` until a rate is known.
+    pub eta: Option<Duration>,
+}
@@ -0,0 +1,405 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::cancel::CancellationToken;
+use crate::error::{ScanError, ScanStage};
+use crate::hasher;
+use crate::models::HashAlgorithm;
+use crate::read_backend::ReadOptions;
+use crate::similarity::{BlockOptions, BlockSignature, BlockSimilarity, SimilarityFinder};
+
+pub const FULL_HASH_NAME: &str = "full_hash";
+pub const PIECE_WISE_NAME: &str = "piece_wise";
+
+/// Whole-file hashing: files with the same digest are duplicates. The digest is the
+/// top-level `algorithm` of the configuration.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct FullHashSettings {
+    /// Bytes read per call while hashing.
+    pub buffer_size: usize,
+}
+
+impl Default for FullHashSettings {
+    fn default() -> Self {
+        Self { buffer_size: 1024 * 1024 }
+    }
+}
+
+/// Fixed-size block matching over the whole file; see [`crate::similarity`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default, deny_unknown_fields)]
+pub struct PieceWiseSettings {
+    /// Bytes per hashed block.
+    pub block_size: usize,
+    /// Which share of matching blocks is compared against `threshold`.
+    pub similarity: BlockSimilarity,
+    /// Share of matching blocks, 0..=1, for two files to be grouped.
+    pub threshold: f64,
+    /// Blocks found in more files than this (zero fill, padding) do not make files candidates.
+    pub max_block_occurrences: usize,
+}
+
+impl Default for PieceWiseSettings {
+    fn default() -> Self {
+        Self { block_size: 4096, similarity: BlockSimilarity::Set, threshold: 0.9, max_block_occurrences: 1024 }
+    }
+}
+
+impl PieceWiseSettings {
+    pub fn block_options(&self) -> BlockOptions {
+        BlockOptions {
+            block_size: self.block_size,
+            min_similarity: self.threshold,
+            similarity: self.similarity,
+            max_block_occurrences: self.max_block_occurrences,
+        }
+    }
+}
+
+/// One pipeline stage: the plugin name under `plugin` plus its settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "plugin", rename_all = "snake_case")]
+pub enum PluginConfig {
+    FullHash(FullHashSettings),
+    PieceWise(PieceWiseSettings),
+}
+
+impl PluginConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PluginConfig::FullHash(_) => FULL_HASH_NAME,
+            PluginConfig::PieceWise(_) => PIECE_WISE_NAME,
+        }
+    }
+
+    /// Every setting outside its allowed range, as `(key, problem)`.
+    pub fn check(&self) -> Vec<(&'static str, String)> {
+        let mut problems = Vec::new();
+        match self {
+            PluginConfig::FullHash(settings) => {
+                if settings.buffer_size == 0 {
+                    problems.push(("buffer_size", "must be at least 1".to_string()));
+                }
+            }
+            PluginConfig::PieceWise(settings) => {
+                if !(512..=16 * 1024 * 1024).contains(&settings.block_size) {
+                    problems.push(("block_size", format!("{} is outside 512..=16777216", settings.block_size)));
+                }
+                if !(0.0..=1.0).contains(&settings.threshold) {
+                    problems.push(("threshold", format!("{} is outside 0..=1", settings.threshold)));
+                }
+                if settings.max_block_occurrences < 2 {
+                    problems.push(("max_block_occurrences", "must be at least 2".to_string()));
+                }
+            }
+        }
+        problems
+    }
+
+    pub fn build(&self, algorithm: HashAlgorithm) -> Box<dyn DeduplicatorPlugin> {
+        match self {
+            PluginConfig::FullHash(settings) => Box::new(FullHashPlugin::new(settings.clone(), algorithm)),
+            PluginConfig::PieceWise(settings) => Box::new(PieceWisePlugin::new(settings.clone())),
+        }
+    }
+}
+
+pub(crate) fn default_plugins() -> Vec<PluginConfig> {
+    vec![PluginConfig::FullHash(FullHashSettings::default())]
+}
+
+/// A file as a plugin saw it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginFile {
+    pub path: PathBuf,
+    pub size: u64,
+    /// This file's own digest, in the plugin's representation.
+    pub hash: String,
+    pub plugin: &'static str,
+}
+
+#[derive(Debug, Default)]
+pub struct PluginResult {
+    pub unique_files: Vec<PluginFile>,
+    pub duplicate_files: Vec<Vec<PluginFile>>,
+    /// Files the plugin could not read; they are in neither list.
+    pub errors: Vec<ScanError>,
+    /// Stages cancelled before finishing; files they did not reach are in none of the lists.
+    pub incomplete_stages: Vec<ScanStage>,
+}
+
+/// A way to represent files and group the ones that match.
+///
+/// Plugins are built by name from [`PluginConfig`], so the trait stays object-safe.
+pub trait DeduplicatorPlugin: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Groups `files`, stopping early once `cancel` is triggered.
+    fn exec(&self, files: &[PathBuf], cancel: &CancellationToken) -> PluginResult;
+}
+
+pub struct FullHashPlugin {
+    settings: FullHashSettings,
+    algorithm: HashAlgorithm,
+}
+
+impl FullHashPlugin {
+    pub fn new(settings: FullHashSettings, algorithm: HashAlgorithm) -> Self {
+        Self { settings, algorithm }
+    }
+}
+
+impl DeduplicatorPlugin for FullHashPlugin {
+    fn name(&self) -> &'static str {
+        FULL_HASH_NAME
+    }
+
+    fn exec(&self, files: &[PathBuf], cancel: &CancellationToken) -> PluginResult {
+        let options = ReadOptions { buffer_size: self.settings.buffer_size, ..ReadOptions::default() };
+        let mut result = PluginResult::default();
+        let mut groups: HashMap<String, Vec<PluginFile>> = HashMap::new();
+        let mut order: Vec<String> = Vec::new();
+        for path in files {
+            let hashed = std::fs::metadata(path).and_then(|metadata| {
+                let hash = hasher::hash_file_observed(path, self.algorithm, None, &options, cancel, &|_| {})?;
+                Ok((metadata.len(), hash.value))
+            });
+            if cancel.is_cancelled() {
+                result.incomplete_stages.push(ScanStage::Hashing);
+                break;
+            }
+            match hashed {
+                Ok((size, hash)) => {
+                    let group = groups.entry(hash.clone()).or_default();
+                    if group.is_empty() {
+                        order.push(hash.clone());
+                    }
+                    group.push(PluginFile { path: path.clone(), size, hash, plugin: FULL_HASH_NAME });
+                }
+                Err(e) => result.errors.push(ScanError::from_io(path, ScanStage::Hashing, &e)),
+            }
+        }
+
+        for hash in order {
+            let group = groups.remove(&hash).expect("every hash has a group");
+            if group.len() == 1 {
+                result.unique_files.extend(group);
+            } else {
+                result.duplicate_files.push(group);
+            }
+        }
+        result
+    }
+}
+
+pub struct PieceWisePlugin {
+    settings: PieceWiseSettings,
+}
+
+impl PieceWisePlugin {
+    pub fn new(settings: PieceWiseSettings) -> Self {
+        Self { settings }
+    }
+}
+
+impl DeduplicatorPlugin for PieceWisePlugin {
+    fn name(&self) -> &'static str {
+        PIECE_WISE_NAME
+    }
+
+    fn exec(&self, files: &[PathBuf], cancel: &CancellationToken) -> PluginResult {
+        let finder = SimilarityFinder::new(self.settings.block_options()).with_cancellation(cancel.clone());
+        let (signatures, errors) = finder.signatures(files);
+        let pairs = finder.match_signatures(&signatures);
+        if cancel.is_cancelled() {
+            return PluginResult { errors, incomplete_stages: vec![ScanStage::Similarity], ..PluginResult::default() };
+        }
+        let index: HashMap<&PathBuf, usize> = signatures.iter().enumerate().map(|(i, s)| (&s.path, i)).collect();
+        let mut similar: HashMap<usize, Vec<usize>> = HashMap::new();
+        for pair in &pairs {
+            let (left, right) = (index[&pair.left], index[&pair.right]);
+            similar.entry(left).or_default().push(right);
+            similar.entry(right).or_default().push(left);
+        }
+
+        // Группа собирается вокруг первого файла: в неё попадают все, кто похож на него не меньше порога
+        let mut result = PluginResult { errors, ..PluginResult::default() };
+        let mut processed = HashSet::new();
+        for i in 0..signatures.len() {
+            if !processed.insert(i) {
+                continue;
+            }
+            let mut group = vec![i];
+            if let Some(others) = similar.get_mut(&i) {
+                others.sort_unstable();
+                group.extend(others.iter().copied().filter(|&other| processed.insert(other)));
+            }
+            let mut members: Vec<PluginFile> = group
+                .into_iter()
+                .map(|member| PluginFile {
+                    path: signatures[member].path.clone(),
+                    size: signatures[member].size,
+                    hash: signature_digest(&signatures[member]),
+                    plugin: PIECE_WISE_NAME,
+                })
+                .collect();
+            if members.len() == 1 {
+                result.unique_files.push(members.remove(0));
+            } else {
+                result.duplicate_files.push(members);
+            }
+        }
+        result
+    }
+}
+
+fn signature_digest(signature: &BlockSignature) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for block in &signature.blocks {
+        hasher.update(block);
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+/// Plugins run in order: each sees only the files the previous ones left unique, and
+/// their duplicate groups add up.
+#[derive(Default)]
+pub struct PluginPipeline {
+    plugins: Vec<Box<dyn DeduplicatorPlugin>>,
+    cancel: CancellationToken,
+}
+
+impl PluginPipeline {
+    /// The pipeline described by `plugins`, hashing with `algorithm`, or every
+    /// out-of-range setting as `plugins[i].key: problem`.
+    pub fn from_config(plugins: &[PluginConfig], algorithm: HashAlgorithm) -> Result<Self, String> {
+        if plugins.is_empty() {
+            return Err("plugins: at least one plugin is required".to_string());
+        }
+        let problems: Vec<String> = plugins
+            .iter()
+            .enumerate()
+            .flat_map(|(i, plugin)| plugin.check().into_iter().map(move |(key, problem)| format!("plugins[{}].{}: {}", i, key, problem)))
+            .collect();
+        if !problems.is_empty() {
+            return Err(problems.join("; "));
+        }
+
+        let mut pipeline = PluginPipeline::default();
+        for plugin in plugins {
+            pipeline.add_plugin(plugin.build(algorithm));
+        }
+        Ok(pipeline)
+    }
+
+    /// Passes `cancel` to every plugin and runs no further plugin once it is triggered.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn add_plugin(&mut self, plugin: Box<dyn DeduplicatorPlugin>) {
+        self.plugins.push(plugin);
+    }
+
+    pub fn remove_plugin(&mut self, name: &str) {
+        self.plugins.retain(|plugin| plugin.name() != name);
+    }
+
+    pub fn plugin_names(&self) -> Vec<&'static str> {
+        self.plugins.iter().map(|plugin| plugin.name()).collect()
+    }
+
+    pub fn execute(&self, files: &[PathBuf]) -> PluginResult {
+        let mut remaining = files.to_vec();
+        let mut result = PluginResult::default();
+        for plugin in &self.plugins {
+            let stage = plugin.exec(&remaining, &self.cancel);
+            remaining = stage.unique_files.iter().map(|f| f.path.clone()).collect();
+            result.duplicate_files.extend(stage.duplicate_files);
+            result.errors.extend(stage.errors);
+            result.unique_files = stage.unique_files;
+            if !stage.incomplete_stages.is_empty() {
+                result.incomplete_stages = stage.incomplete_stages;
+                break;
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pipeline_is_built_from_config_and_groups_by_content() {
+        let dir = tempdir().unwrap();
+        let files: Vec<PathBuf> = [("a", "same"), ("b", "same"), ("c", "other")]
+            .iter()
+            .map(|(name, content)| {
+                let path = dir.path().join(name);
+                std::fs::write(&path, content).unwrap();
+                path
+            })
+            .chain([dir.path().join("missing")])
+            .collect();
+
+        let pipeline = PluginPipeline::from_config(&default_plugins(), HashAlgorithm::MD5).unwrap();
+        assert_eq!(pipeline.plugin_names(), vec![FULL_HASH_NAME]);
+        let result = pipeline.execute(&files);
+        assert_eq!(result.duplicate_files.len(), 1);
+        assert_eq!(result.duplicate_files[0].iter().map(|f| f.path.clone()).collect::<Vec<_>>(), files[..2]);
+        // MD5 из общей настройки `algorithm`: 32 шестнадцатеричных знака
+        assert_eq!(result.duplicate_files[0][0].hash.len(), 32);
+        assert_eq!(result.unique_files.len(), 1);
+        assert_eq!(result.errors.len(), 1);
+        assert!(result.incomplete_stages.is_empty());
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let cancelled = PluginPipeline::from_config(&default_plugins(), HashAlgorithm::MD5).unwrap().with_cancellation(cancel).execute(&files);
+        assert_eq!(cancelled.incomplete_stages, vec![ScanStage::Hashing]);
+        assert!(cancelled.duplicate_files.is_empty() && cancelled.errors.is_empty());
+
+        let bad = [PluginConfig::FullHash(FullHashSettings { buffer_size: 0 })];
+        assert_eq!(PluginPipeline::from_config(&bad, HashAlgorithm::SHA256).err().unwrap(), "plugins[0].buffer_size: must be at least 1");
+        assert!(PluginPipeline::from_config(&[], HashAlgorithm::SHA256).is_err());
+    }
+
+    #[test]
+    fn test_piece_wise_groups_similar_files_each_with_its_own_hash() {
+        let dir = tempdir().unwrap();
+        let write = |name: &str, blocks: &[u8]| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, blocks.iter().flat_map(|&b| vec![b; 512]).collect::<Vec<u8>>()).unwrap();
+            path
+        };
+        let files = vec![write("a", &[1, 2, 3, 4]), write("b", &[1, 2, 3, 5]), write("c", &[6, 7, 8, 9]), write("empty", &[])];
+
+        let settings = PieceWiseSettings { block_size: 512, threshold: 0.5, ..Default::default() };
+        let result = PieceWisePlugin::new(settings.clone()).exec(&files, &CancellationToken::new());
+        assert_eq!(result.duplicate_files.len(), 1);
+        let group = &result.duplicate_files[0];
+        assert_eq!(group.iter().map(|f| f.path.clone()).collect::<Vec<_>>(), files[..2]);
+        assert_ne!(group[0].hash, group[1].hash);
+        assert_eq!(group[1].size, 4 * 512);
+        let unique: Vec<_> = result.unique_files.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(unique, files[2..]);
+
+        let positional = PieceWiseSettings { similarity: BlockSimilarity::Positional, ..settings };
+        let shifted = vec![files[0].clone(), write("shifted", &[0, 1, 2, 3, 4])];
+        assert!(PieceWisePlugin::new(positional).exec(&shifted, &CancellationToken::new()).duplicate_files.is_empty());
+
+        let bad = [PluginConfig::PieceWise(PieceWiseSettings { block_size: 1, ..Default::default() })];
+        assert!(PluginPipeline::from_config(&bad, HashAlgorithm::SHA256).err().unwrap().starts_with("plugins[0].block_size"));
+    }
+
+    #[test]
+    fn test_unknown_plugins_are_rejected_when_parsed() {
+        let error = serde_json::from_str::<PluginConfig>(r#"{"plugin": "minhash"}"#).unwrap_err();
+        assert!(error.to_string().contains("expected `full_hash` or `piece_wise`"), "{}", error);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::events::EventBus;
+use crate::models::{ProgressUpdate, StageProgress};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Minimum gap between two intermediate updates from the same stage.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Weight of the newest sample in the smoothed rate.
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Which update a tracker publishes.
+#[derive(Debug, Clone)]
+pub enum TrackedStage {
+    Hashing { partial: bool },
+    Processing(String),
+}
+
+/// Counts files and bytes for one detection stage and publishes rate-limited progress.
+///
+/// Workers call [`ProgressTracker::advance`] as they read and [`ProgressTracker::file_done`]
+/// per file, from any thread. At most one update per interval is published; the one that
+/// wins the race reports its own current file. [`ProgressTracker::finish`] always publishes.
+pub struct ProgressTracker<'a> {
+    events: &'a EventBus,
+    stage: TrackedStage,
+    interval: Duration,
+    enabled: bool,
+    started: Instant,
+    files_done: AtomicU64,
+    total_files: AtomicU64,
+    bytes_done: AtomicU64,
+    total_bytes: AtomicU64,
+    window: Mutex<RateWindow>,
+}
+
+struct RateWindow {
+    at: Instant,
+    bytes: u64,
+    rate: Option<f64>,
+    last_file: PathBuf,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub fn new(events: &'a EventBus, stage: TrackedStage) -> Self {
+        let started = Instant::now();
+        ProgressTracker {
+            events,
+            stage,
+            interval: DEFAULT_INTERVAL,
+            enabled: events.has_listeners(),
+            started,
+            files_done: AtomicU64::new(0),
+            total_files: AtomicU64::new(0),
+            bytes_done: AtomicU64::new(0),
+            total_bytes: AtomicU64::new(0),
+            window: Mutex::new(RateWindow {
+                at: started,
+                bytes: 0,
+                rate: None,
+                last_file: PathBuf::new(),
+            }),
+        }
+    }
+
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Adds work to the totals; called up front, or as files are queued for streaming stages.
+    pub fn add_work(&self, files: u64, bytes: u64) {
+        self.total_files.fetch_add(files, Ordering::Relaxed);
+        self.total_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, bytes: u64, current_file: &Path) {
+        self.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.maybe_publish(current_file);
+    }
+
+    pub fn file_done(&self, current_file: &Path) {
+        self.files_done.fetch_add(1, Ordering::Relaxed);
+        self.maybe_publish(current_file);
+    }
+
+    /// Publishes the final numbers regardless of the interval, naming the last reported file.
+    pub fn finish(&self) {
+        if !self.enabled {
+            return;
+        }
+        let mut window = self.window.lock().unwrap();
+        let progress = self.snapshot(&mut window, Instant::now());
+        let last_file = window.last_file.clone();
+        drop(window);
+        self.publish(last_file, progress);
+    }
+
+    fn maybe_publish(&self, current_file: &Path) {
+        if !self.enabled {
+            return;
+        }
+        // Another worker is already publishing; this sample is not needed.
+        let Ok(mut window) = self.window.try_lock() else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(window.at) < self.interval {
+            return;
+        }
+        let progress = self.snapshot(&mut window, now);
+        window.last_file = current_file.to_path_buf();
+        drop(window);
+        self.publish(current_file.to_path_buf(), progress);
+    }
+
+    fn snapshot(&self, window: &mut RateWindow, now: Instant) -> StageProgress {
+        let bytes_done = self.bytes_done.load(Ordering::Relaxed);
+        let total_bytes = self.total_bytes.load(Ordering::Relaxed);
+
+        let elapsed = now.duration_since(window.at).as_secs_f64();
+        if elapsed > 0.0 {
+            let sample = bytes_done.saturating_sub(window.bytes) as f64 / elapsed;
+            window.rate = Some(match window.rate {
+                Some(rate) => rate + RATE_SMOOTHING * (sample - rate),
+                None => sample,
+            });
+        }
+        window.at = now;
+        window.bytes = bytes_done;
+
+        // Fall back to the average before the first full window.
+        let bytes_per_second = window.rate.unwrap_or_else(|| {
+            bytes_done as f64
+                / now
+                    .duration_since(self.started)
+                    .as_secs_f64()
+                    .max(f64::EPSILON)
+        });
+        let remaining = total_bytes.saturating_sub(bytes_done);
+        let eta = if remaining == 0 {
+            Some(Duration::ZERO)
+        } else if bytes_per_second > 0.0 {
+            Some(Duration::from_secs_f64(remaining as f64 / bytes_per_second))
+        } else {
+            None
+        };
+
+        StageProgress {
+            files_done: self.files_done.load(Ordering::Relaxed),
+            total_files: self.total_files.load(Ordering::Relaxed),
+            bytes_done,
+            total_bytes,
+            bytes_per_second,
+            eta,
+        }
+    }
+
+    fn publish(&self, current_file: PathBuf, progress: StageProgress) {
+        self.events.publish(match &self.stage {
+            TrackedStage::Hashing { partial } => ProgressUpdate::Hashing {
+                partial: *partial,
+                current_file,
+                progress,
+            },
+            TrackedStage::Processing(stage) => ProgressUpdate::Processing {
+                stage: stage.clone(),
+                current_file,
+                progress,
+            },
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_updates_are_rate_limited_and_final_is_exact() {
+        let bus = EventBus::new();
+        let updates = bus.subscribe(1024);
+        let tracker = ProgressTracker::new(&bus, TrackedStage::Hashing { partial: false })
+            .with_interval(Duration::from_secs(3600));
+        tracker.add_work(100, 100 * 1024);
+
+        for _ in 0..100 {
+            tracker.advance(1024, Path::new("f"));
+            tracker.file_done(Path::new("f"));
+        }
+        assert!(updates.try_recv().is_none());
+
+        tracker.finish();
+        let Some(ProgressUpdate::Hashing {
+            partial, progress, ..
+        }) = updates.try_recv()
+        else {
+            panic!("expected a hashing update");
+        };
+        assert!(!partial);
+        assert_eq!(progress.files_done, 100);
+        assert_eq!(progress.bytes_done, progress.total_bytes);
+        assert_eq!(progress.eta, Some(Duration::ZERO));
+        assert!(progress.bytes_per_second > 0.0);
+    }
+}
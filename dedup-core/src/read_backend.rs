@@ -0,0 +1,344 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::cancel::CancellationToken;
+
+const PAGE_SIZE: usize = 4096;
+
+/// How file contents are read for hashing.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadBackend {
+    /// Plain `read` calls into a page-aligned buffer.
+    #[default]
+    Buffered,
+    /// Memory-maps files of at least `mmap_threshold` bytes; smaller files are read buffered.
+    /// A file truncated by another process while mapped kills the process with SIGBUS,
+    /// so prefer this for archives that are not being written to.
+    Mmap,
+    /// Double-buffered io_uring reads (Linux, `io_uring` feature). Falls back to buffered
+    /// reads when unavailable, e.g. without the feature or under a seccomp filter.
+    IoUring,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct ReadOptions {
+    pub backend: ReadBackend,
+    /// Bytes per read; rounded up to whole pages.
+    pub buffer_size: usize,
+    pub mmap_threshold: u64,
+    /// Tell the kernel reads are sequential, and drop fully read files from the page cache
+    /// afterwards so a scan does not evict everything else (Linux only).
+    pub fadvise: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            backend: ReadBackend::Buffered,
+            buffer_size: 1024 * 1024,
+            mmap_threshold: 16 * 1024 * 1024,
+            fadvise: true,
+        }
+    }
+}
+
+/// Feeds the file, or its first `limit` bytes, to `consume` in chunks of at most
+/// `options.buffer_size` bytes. Stops with `ErrorKind::Interrupted` once `cancel` is set.
+pub(crate) fn read_file(
+    path: &Path,
+    limit: Option<u64>,
+    options: &ReadOptions,
+    cancel: Option<&CancellationToken>,
+    consume: &mut dyn FnMut(&[u8]),
+) -> io::Result<()> {
+    let file = File::open(path)?;
+    let length = file.metadata()?.len();
+    let wanted = limit.map_or(length, |limit| limit.min(length));
+    // Small reads get a small buffer; the size is only a hint since the file may grow.
+    let buffer_size = options.buffer_size.min(wanted as usize).max(PAGE_SIZE);
+    let _hints = options.fadvise.then(|| CacheHints::new(&file, limit.is_none()));
+
+    match options.backend {
+        ReadBackend::Mmap if wanted >= options.mmap_threshold && wanted > 0 => {
+            read_mmap(&file, wanted, buffer_size, cancel, consume)
+        }
+        ReadBackend::IoUring => read_io_uring(&file, limit, buffer_size, cancel, consume),
+        _ => read_buffered(&file, limit, buffer_size, cancel, consume),
+    }
+}
+
+/// Feeds the given `(offset, length)` ranges to `consume`, in order. Ranges past the end of
+/// the file are cut short rather than reported as errors.
+pub(crate) fn read_ranges(
+    path: &Path,
+    ranges: &[(u64, u64)],
+    options: &ReadOptions,
+    cancel: Option<&CancellationToken>,
+    consume: &mut dyn FnMut(&[u8]),
+) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let longest = ranges.iter().map(|(_, length)| *length).max().unwrap_or(0);
+    let mut buffer = AlignedBuffer::new(options.buffer_size.min(longest as usize).max(PAGE_SIZE));
+
+    for &(offset, length) in ranges {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = (&file).take(length);
+        loop {
+            check_cancelled(cancel)?;
+            let bytes_read = match reader.read(buffer.as_mut_slice()) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            consume(&buffer.as_slice()[..bytes_read]);
+        }
+    }
+    Ok(())
+}
+
+fn check_cancelled(cancel: Option<&CancellationToken>) -> io::Result<()> {
+    if cancel.is_some_and(|c| c.is_cancelled()) {
+        return Err(io::Error::new(io::ErrorKind::Interrupted, "hashing cancelled"));
+    }
+    Ok(())
+}
+
+fn read_buffered(
+    file: &File,
+    limit: Option<u64>,
+    buffer_size: usize,
+    cancel: Option<&CancellationToken>,
+    consume: &mut dyn FnMut(&[u8]),
+) -> io::Result<()> {
+    let mut reader = file.take(limit.unwrap_or(u64::MAX));
+    let mut buffer = AlignedBuffer::new(buffer_size);
+
+    loop {
+        check_cancelled(cancel)?;
+        let bytes_read = match reader.read(buffer.as_mut_slice()) {
+            Ok(0) => return Ok(()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        consume(&buffer.as_slice()[..bytes_read]);
+    }
+}
+
+fn read_mmap(
+    file: &File,
+    wanted: u64,
+    chunk_size: usize,
+    cancel: Option<&CancellationToken>,
+    consume: &mut dyn FnMut(&[u8]),
+) -> io::Result<()> {
+    // SAFETY: the mapping is only read, and only while `file` is open. Concurrent writes
+    // change what is hashed, as they would with `read`; truncation is covered in the
+    // `ReadBackend::Mmap` docs.
+    let map = unsafe { memmap2::Mmap::map(file)? };
+    #[cfg(unix)]
+    let _ = map.advise(memmap2::Advice::Sequential);
+
+    let end = (wanted as usize).min(map.len());
+    for chunk in map[..end].chunks(chunk_size) {
+        check_cancelled(cancel)?;
+        consume(chunk);
+    }
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", feature = "io_uring"))]
+fn read_io_uring(
+    file: &File,
+    limit: Option<u64>,
+    buffer_size: usize,
+    cancel: Option<&CancellationToken>,
+    consume: &mut dyn FnMut(&[u8]),
+) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    use io_uring::{opcode, types, IoUring};
+
+    let Ok(mut ring) = IoUring::new(2) else {
+        return read_buffered(file, limit, buffer_size, cancel, consume);
+    };
+    let fd = types::Fd(file.as_raw_fd());
+    let end = limit.unwrap_or(u64::MAX);
+    let mut buffers = [AlignedBuffer::new(buffer_size), AlignedBuffer::new(buffer_size)];
+
+    let submit = |ring: &mut IoUring, buffer: &mut AlignedBuffer, offset: u64| -> io::Result<()> {
+        let len = (buffer.len() as u64).min(end - offset) as u32;
+        let read = opcode::Read::new(fd, buffer.as_mut_slice().as_mut_ptr(), len).offset(offset).build();
+        // SAFETY: the buffer stays alive and untouched until its completion is reaped below;
+        // every submitted read is waited for before returning, even on error or cancellation.
+        unsafe { ring.submission().push(&read) }.map_err(io::Error::other)?;
+        ring.submit()?;
+        Ok(())
+    };
+    let wait = |ring: &mut IoUring| -> io::Result<usize> {
+        ring.submit_and_wait(1)?;
+        let completion = ring.completion().next().expect("a completion after waiting for one");
+        if completion.result() < 0 {
+            return Err(io::Error::from_raw_os_error(-completion.result()));
+        }
+        Ok(completion.result() as usize)
+    };
+
+    if end == 0 {
+        return Ok(());
+    }
+    let mut offset = 0;
+    let mut current = 0;
+    submit(&mut ring, &mut buffers[current], offset)?;
+
+    loop {
+        let bytes_read = wait(&mut ring)?;
+        if bytes_read == 0 {
+            return Ok(());
+        }
+        let next_offset = offset + bytes_read as u64;
+        // Read the next chunk while this one is hashed.
+        let in_flight = next_offset < end;
+        if in_flight {
+            let [first, second] = &mut buffers;
+            submit(&mut ring, if current == 0 { second } else { first }, next_offset)?;
+        }
+
+        let cancelled = check_cancelled(cancel);
+        if cancelled.is_ok() {
+            consume(&buffers[current].as_slice()[..bytes_read]);
+        }
+        if cancelled.is_err() || !in_flight {
+            if in_flight {
+                let _ = wait(&mut ring);
+            }
+            return cancelled;
+        }
+        offset = next_offset;
+        current = 1 - current;
+    }
+}
+
+#[cfg(not(all(target_os = "linux", feature = "io_uring")))]
+fn read_io_uring(
+    file: &File,
+    limit: Option<u64>,
+    buffer_size: usize,
+    cancel: Option<&CancellationToken>,
+    consume: &mut dyn FnMut(&[u8]),
+) -> io::Result<()> {
+    read_buffered(file, limit, buffer_size, cancel, consume)
+}
+
+#[repr(C, align(4096))]
+#[derive(Clone, Copy)]
+struct Page([u8; PAGE_SIZE]);
+
+/// Page-aligned read buffer, as O_DIRECT-style and io_uring reads prefer.
+struct AlignedBuffer {
+    pages: Vec<Page>,
+}
+
+impl AlignedBuffer {
+    fn new(size: usize) -> Self {
+        AlignedBuffer { pages: vec![Page([0; PAGE_SIZE]); size.div_ceil(PAGE_SIZE).max(1)] }
+    }
+
+    #[cfg_attr(not(all(target_os = "linux", feature = "io_uring")), allow(dead_code))]
+    fn len(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: `Page` is a `repr(C)` wrapper around bytes with no padding, so the pages
+        // are one contiguous, initialized byte range.
+        unsafe { std::slice::from_raw_parts(self.pages.as_ptr().cast(), self.pages.len() * PAGE_SIZE) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: as in `as_slice`.
+        unsafe { std::slice::from_raw_parts_mut(self.pages.as_mut_ptr().cast(), self.pages.len() * PAGE_SIZE) }
+    }
+}
+
+/// `posix_fadvise` hints for the duration of a read: sequential access while it lasts,
+/// and the file's pages dropped from the cache afterwards when it was read in full.
+struct CacheHints<'a> {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    file: &'a File,
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    drop_after: bool,
+}
+
+impl<'a> CacheHints<'a> {
+    fn new(file: &'a File, drop_after: bool) -> Self {
+        #[cfg(target_os = "linux")]
+        advise(file, libc::POSIX_FADV_SEQUENTIAL);
+        CacheHints { file, drop_after }
+    }
+}
+
+impl Drop for CacheHints<'_> {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if self.drop_after {
+            advise(self.file, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+}
+
+/// Hints are best effort; failures are ignored.
+#[cfg(target_os = "linux")]
+fn advise(file: &File, advice: libc::c_int) {
+    use std::os::fd::AsRawFd;
+    // SAFETY: plain syscall on a descriptor that is open for the duration of the call.
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn read_all(path: &Path, limit: Option<u64>, options: &ReadOptions) -> Vec<u8> {
+        let mut data = Vec::new();
+        read_file(path, limit, options, None, &mut |chunk| data.extend_from_slice(chunk)).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_backends_read_the_same_bytes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.bin");
+        let content: Vec<u8> = (0..50_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&path, &content).unwrap();
+
+        for backend in [ReadBackend::Buffered, ReadBackend::Mmap, ReadBackend::IoUring] {
+            let options = ReadOptions { backend, buffer_size: 8192, mmap_threshold: 0, fadvise: true };
+            assert_eq!(read_all(&path, None, &options), content, "{backend:?}");
+            assert_eq!(read_all(&path, Some(10_000), &options), &content[..10_000], "{backend:?}");
+        }
+    }
+
+    #[test]
+    fn test_empty_file_and_cancellation() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("empty.bin");
+        fs::write(&path, "").unwrap();
+        let mmap = ReadOptions { backend: ReadBackend::Mmap, mmap_threshold: 0, ..ReadOptions::default() };
+        assert!(read_all(&path, None, &mmap).is_empty());
+
+        fs::write(&path, "data").unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let error = read_file(&path, None, &ReadOptions::default(), Some(&cancel), &mut |_| {}).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Interrupted);
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::{Path, PathBuf};
+use crate::models::{DuplicateGroup, FileHash, FileMetadata, VerificationMethod};
+
+/// Protected roots for the "is this already archived?" workflow.
+///
+/// Files under a reference root are never candidates for removal; a duplicate group only
+/// matters when it has at least one reference file and one target file. When a file lies
+/// under both a reference and a target root, the reference side wins.
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceSet {
+    roots: Vec<PathBuf>,
+}
+
+/// Target files whose content already exists in the reference set.
+#[derive(Debug, Clone)]
+pub struct ReferenceMatch {
+    pub hash: FileHash,
+    /// Size of each file.
+    pub size: u64,
+    pub references: Vec<FileMetadata>,
+    pub targets: Vec<FileMetadata>,
+    pub verification: VerificationMethod,
+}
+
+impl ReferenceSet {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        ReferenceSet { roots }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roots.is_empty()
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Whether a group has files on both sides, i.e. can still produce a match.
+    pub(crate) fn is_mixed(&self, files: &[FileMetadata]) -> bool {
+        let references = files.iter().filter(|f| self.contains(&f.path)).count();
+        references > 0 && references < files.len()
+    }
+
+    /// Splits each mixed group into its reference and target files; other groups are skipped.
+    pub fn matches(&self, groups: &[DuplicateGroup]) -> Vec<ReferenceMatch> {
+        groups
+            .iter()
+            .filter(|group| self.is_mixed(&group.files))
+            .map(|group| {
+                let (references, targets) = group.files.iter().cloned().partition(|f| self.contains(&f.path));
+                ReferenceMatch {
+                    hash: group.hash.clone(),
+                    size: group.files[0].size,
+                    references,
+                    targets,
+                    verification: group.verification,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+    use crate::duplicates::DuplicateFinder;
+    use crate::models::HashAlgorithm;
+
+    fn metadata(path: &Path) -> FileMetadata {
+        FileMetadata { path: path.to_path_buf(), size: fs::metadata(path).unwrap().len(), hash: None, modified: None, created: None }
+    }
+
+    #[test]
+    fn test_only_target_files_with_a_reference_copy_are_matched() {
+        let dir = tempdir().unwrap();
+        let archive = dir.path().join("archive");
+        let usb = dir.path().join("usb");
+        fs::create_dir_all(&archive).unwrap();
+        fs::create_dir_all(&usb).unwrap();
+
+        fs::write(archive.join("photo.jpg"), "photo bytes").unwrap();
+        fs::write(archive.join("photo copy.jpg"), "photo bytes").unwrap();
+        fs::write(usb.join("IMG_0001.jpg"), "photo bytes").unwrap();
+        // Duplicated only within the target, or only within the archive: not reported.
+        fs::write(usb.join("new1.txt"), "fresh content").unwrap();
+        fs::write(usb.join("new2.txt"), "fresh content").unwrap();
+        fs::write(archive.join("old1.txt"), "older content").unwrap();
+        fs::write(archive.join("old2.txt"), "older content").unwrap();
+
+        let files = ["archive/photo.jpg", "archive/photo copy.jpg", "usb/IMG_0001.jpg", "usb/new1.txt", "usb/new2.txt", "archive/old1.txt", "archive/old2.txt"]
+            .iter()
+            .map(|name| metadata(&dir.path().join(name)))
+            .collect();
+
+        let references = Arc::new(ReferenceSet::new(vec![archive.clone()]));
+        let report = DuplicateFinder::new(HashAlgorithm::SHA256)
+            .with_reference_set(Arc::clone(&references))
+            .find_duplicates(files);
+        assert_eq!(report.groups.len(), 1);
+
+        let matches = references.matches(&report.groups);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].references.len(), 2);
+        assert_eq!(matches[0].targets.len(), 1);
+        assert_eq!(matches[0].targets[0].path, usb.join("IMG_0001.jpg"));
+    }
+}
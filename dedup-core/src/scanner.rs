@@ -1,171 +1,353 @@
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
 use walkdir::{WalkDir, DirEntry};
-use rayon::prelude::*;
 pub use crate::config::ScanConfig;
-use crate::models::{FileMetadata, ProgressUpdate, ScanStats};
+use crate::cancel::CancellationToken;
+use crate::error::{ScanError, ScanStage};
+use crate::events::EventBus;
+use crate::models::{FileMetadata, ProgressUpdate, ScanResult, ScanStats};
+use crate::walker::ParallelWalker;
+
+pub type ScanItem = Result<FileMetadata, ScanError>;
 
 pub struct Scanner {
-    config: ScanConfig,
-    progress_callback: Option<Box<dyn Fn(ProgressUpdate) + Send + Sync + 'static>>,
+    config: Arc<ScanConfig>,
+    events: Arc<EventBus>,
+    cancel: CancellationToken,
 }
 
 impl Scanner {
     pub fn new(config: ScanConfig, progress_callback: Option<Box<dyn Fn(ProgressUpdate) + Send + Sync + 'static>>) -> Self {
-        Scanner { config, progress_callback }
+        let events = EventBus::new();
+        if let Some(callback) = progress_callback {
+            events.on_event(callback);
+        }
+        Scanner { config: Arc::new(config), events: Arc::new(events), cancel: CancellationToken::new() }
+    }
+
+    /// Stops the walk once `cancel` is triggered; the result is then marked incomplete.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Progress updates for this scanner; subscribe before starting a scan.
+    /// Clone the handle to report later stages, e.g. hashing, to the same listeners.
+    pub fn events(&self) -> &Arc<EventBus> {
+        &self.events
+    }
+
+    pub fn scan(&self) -> Result<ScanResult, Box<dyn std::error::Error>> {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+
+        let stats = walk(&self.config, &self.events, &self.cancel, |item| {
+            match item {
+                Ok(file) => files.push(file),
+                Err(e) => errors.push(e),
+            }
+            true
+        });
+
+        Ok(self.finish_result(files, errors, stats))
+    }
+
+    /// Walks the roots with the parallel walker and yields entries as they are found.
+    ///
+    /// At most `buffer` entries are queued; the walk pauses until the consumer catches up.
+    /// Dropping the stream stops the walk. Entries arrive unsorted.
+    pub fn stream(&self, buffer: usize) -> Result<ScanStream, Box<dyn std::error::Error>> {
+        let walker = ParallelWalker::new(Arc::clone(&self.config), Arc::clone(&self.events), self.cancel.clone())?;
+        let events = Arc::clone(&self.events);
+        let (sender, receiver) = mpsc::sync_channel(buffer.max(1));
+
+        let worker = thread::spawn(move || {
+            let stats = walker.walk(|item| sender.send(item).is_ok());
+            events.publish(ProgressUpdate::Finished(stats.clone()));
+            stats
+        });
+
+        Ok(ScanStream { receiver, worker })
     }
 
-    pub fn scan(&self) -> Result<Vec<FileMetadata>, Box<dyn std::error::Error>> {
-        let mut entries = Vec::new();
-        let mut stats = ScanStats::new();
+    /// Like [`Scanner::scan`], but walks directories in parallel on `config.threads` workers.
+    pub fn scan_parallel(&self) -> Result<ScanResult, Box<dyn std::error::Error>> {
+        let walker = ParallelWalker::new(Arc::clone(&self.config), Arc::clone(&self.events), self.cancel.clone())?;
+        let items = Mutex::new(Vec::new());
 
-        for path in &self.config.root_paths {
-            let mut files = self.walk_directory(path, &mut stats).collect::<Vec<_>>();
-            entries.append(&mut files);
+        let stats = walker.walk(|item| {
+            items.lock().unwrap().push(item);
+            true
+        });
+
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        for item in items.into_inner().unwrap() {
+            match item {
+                Ok(file) => files.push(file),
+                Err(e) => errors.push(e),
+            }
         }
 
-        if let Some(callback) = &self.progress_callback {
-            callback(ProgressUpdate::Finished(stats));
+        self.events.publish(ProgressUpdate::Finished(stats.clone()));
+        Ok(self.finish_result(files, errors, stats))
+    }
+
+    fn finish_result(&self, mut files: Vec<FileMetadata>, mut errors: Vec<ScanError>, stats: ScanStats) -> ScanResult {
+        if self.config.sort_output {
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            errors.sort_by(|a, b| a.path.cmp(&b.path));
         }
 
-        Ok(entries)
+        let incomplete_stages = if self.cancel.is_cancelled() { vec![ScanStage::Walk] } else { Vec::new() };
+
+        ScanResult { files, errors, stats, incomplete_stages }
     }
+}
 
-    fn walk_directory<'a>(&'a self, root: &Path, stats: &'a mut ScanStats) -> impl Iterator<Item = FileMetadata> + 'a {
-        let walker = WalkDir::new(root)
-            .max_depth(self.config.max_depth.unwrap_or(usize::MAX))
-            .follow_links(self.config.follow_symlinks)
-            .into_iter()
-            .filter_entry(|e| !self.should_skip(e))
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file());
+/// Entries produced by [`Scanner::stream`], in discovery order.
+pub struct ScanStream {
+    receiver: Receiver<ScanItem>,
+    worker: JoinHandle<ScanStats>,
+}
 
-        walker.filter_map(move |entry| {
-            stats.files_scanned += 1;
+impl Iterator for ScanStream {
+    type Item = ScanItem;
 
-            if let Some(callback) = &self.progress_callback {
-                callback(ProgressUpdate::Scanning {
-                    current_path: entry.path().to_path_buf(),
-                    files_scanned: stats.files_scanned,
-                });
-            }
+    fn next(&mut self) -> Option<ScanItem> {
+        self.receiver.recv().ok()
+    }
+}
 
-            self.create_metadata(entry, stats)
-        })
+impl ScanStream {
+    /// Stops consuming, waits for the walker and returns its statistics.
+    pub fn finish(self) -> ScanStats {
+        let ScanStream { receiver, worker } = self;
+        drop(receiver);
+        worker.join().expect("scanner thread panicked")
     }
+}
 
-    fn should_skip(&self, entry: &DirEntry) -> bool {
-        let path = entry.path();
+/// Walks every root in order, handing each file or error to `emit` until it returns `false`
+/// or the scan is cancelled.
+fn walk<F>(config: &ScanConfig, events: &EventBus, cancel: &CancellationToken, mut emit: F) -> ScanStats
+where
+    F: FnMut(ScanItem) -> bool,
+{
+    let mut stats = ScanStats::new();
 
-        if self.config.exclude_patterns.iter().any(|pattern| pattern.matches_path(path)) {
-            return true;
-        }
+    'roots: for root in config.walk_roots() {
+        for entry in walk_directory(config, root) {
+            if cancel.is_cancelled() {
+                break 'roots;
+            }
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    stats.errors += 1;
+                    if !emit(Err(ScanError::from_walkdir(root, &e))) {
+                        break 'roots;
+                    }
+                    continue;
+                }
+            };
+
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            stats.files_scanned += 1;
+            events.publish(ProgressUpdate::Scanning {
+                current_path: entry.path().to_path_buf(),
+                files_scanned: stats.files_scanned,
+            });
+
+            let metadata = entry.metadata().map_err(|e| ScanError::from_walkdir(root, &e));
+            let item = match create_metadata(config, entry.into_path(), metadata) {
+                Ok(Some(file)) => {
+                    stats.total_size += file.size;
+                    Ok(file)
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    stats.errors += 1;
+                    Err(e)
+                }
+            };
 
-        if let Some(name) = path.file_name() {
-            if name.to_string_lossy().starts_with('.') {
-                return true;
+            if !emit(item) {
+                break 'roots;
             }
         }
+    }
+
+    events.publish(ProgressUpdate::Finished(stats.clone()));
+    stats
+}
+
+fn walk_directory<'a>(config: &'a ScanConfig, root: &Path) -> impl Iterator<Item = walkdir::Result<DirEntry>> + 'a {
+    WalkDir::new(root)
+        .max_depth(config.max_depth.unwrap_or(usize::MAX))
+        .follow_links(config.follow_symlinks)
+        .into_iter()
+        .filter_entry(move |e| !should_skip(config, e))
+}
 
-        false
+fn should_skip(config: &ScanConfig, entry: &DirEntry) -> bool {
+    is_skipped(config, entry.path(), entry.depth())
+}
+
+/// Exclude-pattern and hidden-entry filter shared by both walkers.
+pub(crate) fn is_skipped(config: &ScanConfig, path: &Path, depth: usize) -> bool {
+    if config.exclude_patterns.iter().any(|pattern| pattern.matches_path(path)) {
+        return true;
     }
 
-    fn create_metadata(&self, entry: DirEntry, stats: &mut ScanStats) -> Option<FileMetadata> {
-        let metadata = match entry.metadata() {
-            Ok(m) => m,
-            Err(_) => return None,
-        };
+    // The roots themselves are never treated as hidden.
+    if config.skip_hidden
+        && depth > 0
+        && let Some(name) = path.file_name()
+        && name.to_string_lossy().starts_with('.') {
+        return true;
+    }
 
-        let size = metadata.len();
+    false
+}
 
-        if let Some(min_size) = self.config.min_file_size {
-            if size < min_size {
-                return None;
-            }
-        }
+/// Builds the metadata record, returning `Ok(None)` for files outside the size limits.
+pub(crate) fn create_metadata(config: &ScanConfig, path: PathBuf, metadata: Result<std::fs::Metadata, ScanError>) -> Result<Option<FileMetadata>, ScanError> {
+    let metadata = metadata?;
+    let size = metadata.len();
 
-        if let Some(max_size) = self.config.max_file_size {
-            if size > max_size {
-                return None;
-            }
+    if let Some(min_size) = config.min_file_size
+        && size < min_size {
+        return Ok(None);
+    }
+
+    if let Some(max_size) = config.max_file_size
+        && size > max_size {
+        return Ok(None);
+    }
+
+    Ok(Some(FileMetadata {
+        path,
+        size,
+        hash: None,
+        modified: metadata.modified().ok(),
+        created: metadata.created().ok(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::models::HashAlgorithm;
+    use crate::error::ScanErrorKind;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn config_for(root: &Path) -> ScanConfig {
+        ScanConfig::build(Config {
+            root_paths: vec![root.to_path_buf()],
+            min_file_size: None,
+            max_file_size: None,
+            follow_symlinks: false,
+            exclude_patterns: vec![],
+            max_depth: None,
+            skip_hidden: true,
+            threads: Some(2),
+            sort_output: true,
+            io_policies: vec![],
+            read: Default::default(),
+            sampling: None,
+            verify_bytes: false,
+            reference_paths: vec![],
+            collapse_directories: false,
+            algorithm: HashAlgorithm::SHA256,
+            plugins: vec![],
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_scan_collects_files() {
+        let dir = tempdir().unwrap();
+        fs::create_dir(dir.path().join("nested")).unwrap();
+        fs::write(dir.path().join("a.txt"), "aaa").unwrap();
+        fs::write(dir.path().join("nested/b.txt"), "bb").unwrap();
+
+        let scanner = Scanner::new(config_for(dir.path()), None);
+        let result = scanner.scan().unwrap();
+        assert_eq!(result.files.len(), 2);
+        assert_eq!(result.stats.total_size, 5);
+        assert!(result.errors.is_empty());
+
+        let parallel = scanner.scan_parallel().unwrap();
+        assert_eq!(parallel.files.len(), 2);
+        assert_eq!(parallel.stats.total_size, 5);
+    }
+
+    #[test]
+    fn test_scan_reports_missing_root() {
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing");
+
+        let result = Scanner::new(config_for(&missing), None).scan().unwrap();
+        assert!(result.files.is_empty());
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].kind, ScanErrorKind::Vanished);
+        assert_eq!(result.errors[0].path, missing);
+        assert_eq!(result.stats.errors, 1);
+    }
+
+    #[test]
+    fn test_stream_yields_files_and_events() {
+        let dir = tempdir().unwrap();
+        for i in 0..20 {
+            fs::write(dir.path().join(format!("{i}.txt")), "x").unwrap();
         }
 
-        stats.total_size += size;
-
-        Some(FileMetadata {
-            path: entry.path().to_path_buf(),
-            size,
-            hash: None,
-            modified: metadata.modified().ok(),
-            created: metadata.created().ok(),
-        })
-    }
-
-    pub fn scan_parallel(&self) -> Result<Vec<FileMetadata>, Box<dyn std::error::Error>> {
-        let stats = std::sync::Arc::new(std::sync::Mutex::new(ScanStats::new()));
-
-        let all_paths: Vec<PathBuf> = self.config.root_paths
-            .iter()
-            .flat_map(|path| {
-                WalkDir::new(path)
-                    .max_depth(self.config.max_depth.unwrap_or(usize::MAX))
-                    .follow_links(self.config.follow_symlinks)
-                    .into_iter()
-                    .filter_entry(|e| !self.should_skip(e))
-                    .filter_map(|e| e.ok())
-                    .filter(|e| e.file_type().is_file())
-                    .map(|e| e.path().to_path_buf())
-            })
-            .collect();
-
-        let results: Vec<FileMetadata> = all_paths
-            .into_par_iter()
-            .filter_map(|path| {
-                let metadata = match std::fs::metadata(&path) {
-                    Ok(m) => m,
-                    Err(_) => return None,
-                };
-
-                let size = metadata.len();
-
-                if let Some(min_size) = self.config.min_file_size {
-                    if size < min_size { return None; }
-                }
+        let scanner = Scanner::new(config_for(dir.path()), None);
+        let events = scanner.events().subscribe(64);
 
-                if let Some(max_size) = self.config.max_file_size {
-                    if size > max_size { return None; }
-                }
+        let mut stream = scanner.stream(2).unwrap();
+        let files: Vec<FileMetadata> = stream.by_ref().map(|item| item.unwrap()).collect();
+        let stats = stream.finish();
 
-                // Update stats
-                {
-                    let mut stats = stats.lock().unwrap();
-                    stats.files_scanned += 1;
-                    stats.total_size += size;
-
-                    // Call progress callback if exists
-                    if let Some(callback) = &self.progress_callback {
-                        callback(ProgressUpdate::Scanning {
-                            current_path: path.clone(),
-                            files_scanned: stats.files_scanned,
-                        });
-                    }
-                }
+        assert_eq!(files.len(), 20);
+        assert_eq!(stats.files_scanned, 20);
+        assert!(matches!(events.try_iter().last(), Some(ProgressUpdate::Finished(_))));
+    }
 
-                Some(FileMetadata {
-                    path,
-                    size,
-                    hash: None,
-                    modified: metadata.modified().ok(),
-                    created: metadata.created().ok(),
-                })
-            })
-            .collect();
-
-        // Send final update
-        if let Some(callback) = &self.progress_callback {
-            let final_stats = stats.lock().unwrap().clone();
-            callback(ProgressUpdate::Finished(final_stats));
+    #[test]
+    fn test_dropping_stream_stops_walk() {
+        let dir = tempdir().unwrap();
+        for i in 0..50 {
+            fs::write(dir.path().join(format!("{i}.txt")), "x").unwrap();
         }
 
-        Ok(results)
+        let mut stream = Scanner::new(config_for(dir.path()), None).stream(1).unwrap();
+        assert!(stream.next().is_some());
+        let stats = stream.finish();
+        assert!(stats.files_scanned < 50);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_cancelled_scan_is_marked_incomplete() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "aaa").unwrap();
+
+        let cancel = CancellationToken::new();
+        let scanner = Scanner::new(config_for(dir.path()), None).with_cancellation(cancel.clone());
+        assert!(scanner.scan().unwrap().is_complete());
+
+        cancel.cancel();
+        for result in [scanner.scan().unwrap(), scanner.scan_parallel().unwrap()] {
+            assert!(result.files.is_empty());
+            assert_eq!(result.incomplete_stages, vec![ScanStage::Walk]);
+        }
+    }
+}
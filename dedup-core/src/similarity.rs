@@ -0,0 +1,426 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use crate::cancel::CancellationToken;
+use crate::error::{ScanError, ScanStage};
+
+/// Method name recorded on pairs found by fixed-block matching.
+pub const BLOCK_METHOD: &str = "block";
+
+pub type BlockHash = [u8; 32];
+
+/// Which share of matching blocks a pair is scored by.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockSimilarity {
+    /// Block `k` only matches block `k` of the other file; for images with stable offsets.
+    Positional,
+    /// A block matches anywhere in the other file, so moved or reordered data still counts.
+    #[default]
+    Set,
+}
+
+/// Fixed-size block matching between files that are not byte-identical.
+#[derive(Debug, Clone)]
+pub struct BlockOptions {
+    pub block_size: usize,
+    /// Pairs below this share of matching blocks are not reported.
+    pub min_similarity: f64,
+    pub similarity: BlockSimilarity,
+    /// Blocks held by more files than this (zero fill, padding) do not make files candidates.
+    pub max_block_occurrences: usize,
+}
+
+impl Default for BlockOptions {
+    fn default() -> Self {
+        Self { block_size: 4096, min_similarity: 0.5, similarity: BlockSimilarity::Set, max_block_occurrences: 1024 }
+    }
+}
+
+/// Hash of every block of one file, in file order; the last block may be short.
+#[derive(Debug, Clone)]
+pub struct BlockSignature {
+    pub path: PathBuf,
+    pub size: u64,
+    pub block_size: usize,
+    pub blocks: Vec<BlockHash>,
+}
+
+impl BlockSignature {
+    pub fn block_len(&self, index: usize) -> u64 {
+        let offset = (index * self.block_size) as u64;
+        self.size.saturating_sub(offset).min(self.block_size as u64)
+    }
+}
+
+/// Bytes that are identical in both files of a pair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MatchedRange {
+    pub left_offset: u64,
+    pub right_offset: u64,
+    pub len: u64,
+}
+
+/// Two files sharing part of their content.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimilarPair {
+    pub left: PathBuf,
+    pub right: PathBuf,
+    pub left_size: u64,
+    pub right_size: u64,
+    /// Score, 0..=1: the share of the larger file's blocks also found in the other file,
+    /// anywhere or, once [`SimilarPair::scored_by`] positional, at the same offset.
+    pub similarity: f64,
+    /// Share of block positions holding the same block in both files.
+    pub positional: f64,
+    /// Which matcher produced the pair, e.g. [`BLOCK_METHOD`].
+    pub method: String,
+    pub ranges: Vec<MatchedRange>,
+}
+
+impl SimilarPair {
+    pub fn shared_bytes(&self) -> u64 {
+        self.ranges.iter().map(|r| r.len).sum()
+    }
+
+    /// The pair with `similarity` measured as `kind`.
+    pub fn scored_by(mut self, kind: BlockSimilarity) -> Self {
+        if kind == BlockSimilarity::Positional {
+            self.similarity = self.positional;
+        }
+        self
+    }
+}
+
+// Один read может вернуть меньше блока и не дойдя до конца файла
+fn read_block<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Hashes `path` in blocks of `block_size` bytes. A `block_size` of zero is rejected with
+/// `ErrorKind::InvalidInput`.
+pub fn block_signature(path: &Path, block_size: usize) -> io::Result<BlockSignature> {
+    read_signature(path, block_size, None)
+}
+
+// Отмена прерывает чтение с `ErrorKind::Interrupted`, как и при хешировании
+fn read_signature(path: &Path, block_size: usize, cancel: Option<&CancellationToken>) -> io::Result<BlockSignature> {
+    if block_size == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "block size must be at least 1"));
+    }
+    let file = File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut buffer = vec![0u8; block_size];
+    let mut blocks = Vec::with_capacity((size as usize).div_ceil(block_size));
+    loop {
+        if cancel.is_some_and(|c| c.is_cancelled()) {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "block hashing cancelled"));
+        }
+        let read = read_block(&mut reader, &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        blocks.push(*blake3::hash(&buffer[..read]).as_bytes());
+        if read < block_size {
+            break;
+        }
+    }
+    Ok(BlockSignature { path: path.to_path_buf(), size, block_size, blocks })
+}
+
+/// Compares two signatures taken with the same block size.
+///
+/// A block matches anywhere in the other file, each block at most once, so moved data
+/// still counts; `ranges` prefer the same offset, then extending the previous range.
+pub fn compare_signatures(left: &BlockSignature, right: &BlockSignature) -> SimilarPair {
+    let total = left.blocks.len().max(right.blocks.len());
+    // Позиции блока в правом файле по возрастанию и курсор на первую, возможно, свободную
+    let mut right_positions: HashMap<&BlockHash, (Vec<usize>, usize)> = HashMap::new();
+    for (position, block) in right.blocks.iter().enumerate() {
+        right_positions.entry(block).or_default().0.push(position);
+    }
+
+    let positional = left.blocks.iter().zip(&right.blocks).filter(|(a, b)| a == b).count();
+    // Each right block pairs with at most one left block, so repeated blocks cannot count twice.
+    // Both checks are constant time, so files made of one repeated block stay linear.
+    let mut used: HashSet<usize> = HashSet::new();
+    let mut matched: Vec<(usize, usize)> = Vec::new();
+    for (position, block) in left.blocks.iter().enumerate() {
+        let Some((positions, cursor)) = right_positions.get_mut(block) else { continue };
+        let free = |r: &usize| !used.contains(r) && right.blocks.get(*r) == Some(block);
+        let continues = matched.last().filter(|&&(l, _)| l + 1 == position).map(|&(_, r)| r + 1);
+        let target = match Some(position).filter(free).or(continues.filter(free)) {
+            Some(target) => target,
+            None => {
+                while positions.get(*cursor).is_some_and(|r| used.contains(r)) {
+                    *cursor += 1;
+                }
+                let Some(&target) = positions.get(*cursor) else { continue };
+                target
+            }
+        };
+        used.insert(target);
+        matched.push((position, target));
+    }
+    let shared = matched.len();
+
+    let mut ranges: Vec<MatchedRange> = Vec::new();
+    let mut last: Option<(usize, usize)> = None;
+    for (l, r) in matched {
+        let len = left.block_len(l);
+        match (last, ranges.last_mut()) {
+            (Some((pl, pr)), Some(range)) if pl + 1 == l && pr + 1 == r => range.len += len,
+            _ => ranges.push(MatchedRange {
+                left_offset: (l * left.block_size) as u64,
+                right_offset: (r * right.block_size) as u64,
+                len,
+            }),
+        }
+        last = Some((l, r));
+    }
+
+    let ratio = |count: usize| if total == 0 { 1.0 } else { count as f64 / total as f64 };
+    SimilarPair {
+        left: left.path.clone(),
+        right: right.path.clone(),
+        left_size: left.size,
+        right_size: right.size,
+        similarity: ratio(shared),
+        positional: ratio(positional),
+        method: BLOCK_METHOD.to_string(),
+        ranges,
+    }
+}
+
+/// Every pair among `files` with at least `min_similarity` of their blocks in common,
+/// scored as `options.similarity`. Unreadable files are returned as errors.
+pub fn find_similar_pairs(files: &[PathBuf], options: &BlockOptions) -> (Vec<SimilarPair>, Vec<ScanError>) {
+    let report = SimilarityFinder::new(options.clone()).find(files);
+    (report.pairs, report.errors)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SimilarityReport {
+    pub pairs: Vec<SimilarPair>,
+    pub errors: Vec<ScanError>,
+    /// [`ScanStage::Similarity`] when cancelled; the pairs found until then are kept.
+    pub incomplete_stages: Vec<ScanStage>,
+}
+
+impl SimilarityReport {
+    pub fn is_complete(&self) -> bool {
+        self.incomplete_stages.is_empty()
+    }
+}
+
+/// Finds similar files by fixed-size block matching, see [`BlockOptions`].
+pub struct SimilarityFinder {
+    options: BlockOptions,
+    cancel: CancellationToken,
+}
+
+impl SimilarityFinder {
+    pub fn new(options: BlockOptions) -> Self {
+        SimilarityFinder { options, cancel: CancellationToken::new() }
+    }
+
+    /// Stops reading and comparing files once `cancel` is triggered.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    pub fn find(&self, files: &[PathBuf]) -> SimilarityReport {
+        let (signatures, errors) = self.signatures(files);
+        let pairs = self.match_signatures(&signatures);
+        let incomplete_stages = if self.cancel.is_cancelled() { vec![ScanStage::Similarity] } else { Vec::new() };
+        SimilarityReport { pairs, errors, incomplete_stages }
+    }
+
+    /// Signatures of the readable files among `files`, in order, and an error for each file
+    /// that could not be read. Empty files have no blocks, so they never match anything.
+    /// Files not read before cancellation are left out without an error.
+    pub fn signatures(&self, files: &[PathBuf]) -> (Vec<BlockSignature>, Vec<ScanError>) {
+        let results: Vec<Option<Result<BlockSignature, ScanError>>> = files
+            .par_iter()
+            .map(|path| {
+                if self.cancel.is_cancelled() {
+                    return None;
+                }
+                match read_signature(path, self.options.block_size, Some(&self.cancel)) {
+                    Err(_) if self.cancel.is_cancelled() => None,
+                    result => Some(result.map_err(|e| ScanError::from_io(path, ScanStage::Similarity, &e))),
+                }
+            })
+            .collect();
+        let mut signatures = Vec::new();
+        let mut errors = Vec::new();
+        for result in results.into_iter().flatten() {
+            match result {
+                Ok(signature) => signatures.push(signature),
+                Err(error) => errors.push(error),
+            }
+        }
+        (signatures, errors)
+    }
+
+    /// Every pair of `signatures` scoring at least `min_similarity`.
+    ///
+    /// Candidates come from an index of block hash to the files holding it, so files with
+    /// nothing in common are never compared.
+    pub fn match_signatures(&self, signatures: &[BlockSignature]) -> Vec<SimilarPair> {
+        let options = &self.options;
+        let mut index: HashMap<&BlockHash, Vec<usize>> = HashMap::new();
+        for (file, signature) in signatures.iter().enumerate() {
+            for block in signature.blocks.iter().collect::<HashSet<_>>() {
+                index.entry(block).or_default().push(file);
+            }
+        }
+        let mut candidates: HashSet<(usize, usize)> = HashSet::new();
+        for holders in index.values() {
+            if holders.len() < 2 || holders.len() > options.max_block_occurrences {
+                continue;
+            }
+            for (i, &left) in holders.iter().enumerate() {
+                candidates.extend(holders[i + 1..].iter().map(|&right| (left, right)));
+            }
+        }
+        let mut candidates: Vec<(usize, usize)> = candidates.into_iter().collect();
+        candidates.sort_unstable();
+
+        candidates
+            .par_iter()
+            .filter(|_| !self.cancel.is_cancelled())
+            .map(|&(left, right)| compare_signatures(&signatures[left], &signatures[right]).scored_by(options.similarity))
+            .filter(|pair| pair.similarity >= options.min_similarity)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const BLOCK: usize = 512;
+
+    fn write(dir: &Path, name: &str, blocks: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        let data: Vec<u8> = blocks.iter().flat_map(|&b| vec![b; BLOCK]).collect();
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_reordered_blocks_match_with_ranges() {
+        let dir = tempdir().unwrap();
+        let a = block_signature(&write(dir.path(), "a", &[1, 2, 3, 4]), BLOCK).unwrap();
+        let b = block_signature(&write(dir.path(), "b", &[3, 4, 1, 2]), BLOCK).unwrap();
+
+        let pair = compare_signatures(&a, &b);
+        assert_eq!(pair.similarity, 1.0);
+        assert_eq!(pair.positional, 0.0);
+        let block = BLOCK as u64;
+        assert_eq!(pair.ranges, vec![
+            MatchedRange { left_offset: 0, right_offset: 2 * block, len: 2 * block },
+            MatchedRange { left_offset: 2 * block, right_offset: 0, len: 2 * block },
+        ]);
+    }
+
+    #[test]
+    fn test_repeated_blocks_match_at_most_once() {
+        let dir = tempdir().unwrap();
+        let a = block_signature(&write(dir.path(), "a", &[1, 1, 1, 1]), BLOCK).unwrap();
+        let b = block_signature(&write(dir.path(), "b", &[1, 2]), BLOCK).unwrap();
+
+        let pair = compare_signatures(&a, &b);
+        assert_eq!(pair.shared_bytes(), BLOCK as u64);
+        assert_eq!(pair.similarity, 0.25);
+        assert_eq!(pair.ranges, vec![MatchedRange { left_offset: 0, right_offset: 0, len: BLOCK as u64 }]);
+
+        let reverse = compare_signatures(&b, &a);
+        assert_eq!(reverse.shared_bytes(), BLOCK as u64);
+    }
+
+    #[test]
+    fn test_cancelled_search_is_incomplete() {
+        let dir = tempdir().unwrap();
+        let files = vec![write(dir.path(), "a", &[1, 2, 3, 4]), write(dir.path(), "b", &[1, 2, 3, 5])];
+        let options = BlockOptions { block_size: BLOCK, ..Default::default() };
+        assert!(SimilarityFinder::new(options.clone()).find(&files).is_complete());
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let report = SimilarityFinder::new(options).with_cancellation(cancel).find(&files);
+        assert_eq!(report.incomplete_stages, vec![ScanStage::Similarity]);
+        assert!(report.pairs.is_empty());
+        assert!(report.errors.is_empty());
+    }
+
+    #[test]
+    fn test_zero_block_size_is_rejected() {
+        let dir = tempdir().unwrap();
+        let error = block_signature(&write(dir.path(), "a", &[1]), 0).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_uniform_files_match_in_linear_time() {
+        // Как образ диска, заполненный нулями: 1 GiB блоками по 4 KiB
+        let zeros = |name: &str| BlockSignature {
+            path: PathBuf::from(name),
+            size: 1 << 30,
+            block_size: 4096,
+            blocks: vec![[0u8; 32]; 1 << 18],
+        };
+        let pair = compare_signatures(&zeros("a"), &zeros("b"));
+        assert_eq!(pair.similarity, 1.0);
+        assert_eq!(pair.ranges, vec![MatchedRange { left_offset: 0, right_offset: 0, len: 1 << 30 }]);
+    }
+
+    #[test]
+    fn test_finds_only_pairs_sharing_blocks() {
+        let dir = tempdir().unwrap();
+        let files = vec![
+            write(dir.path(), "a", &[1, 2, 3, 4]),
+            write(dir.path(), "b", &[1, 2, 3, 5]),
+            write(dir.path(), "c", &[6, 7, 8, 9]),
+        ];
+        let mut tail = std::fs::read(&files[0]).unwrap();
+        tail.extend_from_slice(b"tail");
+        std::fs::write(dir.path().join("d"), tail).unwrap();
+
+        let options = BlockOptions { block_size: BLOCK, ..Default::default() };
+        let (pairs, errors) = find_similar_pairs(&[files.clone(), vec![dir.path().join("d")]].concat(), &options);
+        assert!(errors.is_empty());
+        let names: Vec<(String, String, f64)> = pairs.iter()
+            .map(|p| (p.left.file_name().unwrap().to_string_lossy().into_owned(), p.right.file_name().unwrap().to_string_lossy().into_owned(), p.similarity))
+            .collect();
+        assert_eq!(names, vec![
+            ("a".into(), "b".into(), 0.75),
+            ("a".into(), "d".into(), 0.8),
+            ("b".into(), "d".into(), 0.6),
+        ]);
+        assert_eq!(pairs[1].shared_bytes(), 4 * BLOCK as u64);
+
+        let positional = BlockOptions { similarity: BlockSimilarity::Positional, ..options };
+        let files = vec![files[0].clone(), write(dir.path(), "shifted", &[0, 1, 2, 3, 4])];
+        assert!(find_similar_pairs(&files, &positional).0.is_empty());
+        let (pairs, _) = find_similar_pairs(&files, &BlockOptions { min_similarity: 0.0, ..positional });
+        assert_eq!((pairs[0].similarity, pairs[0].positional), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use glob::Pattern;
+use serde_json::{Map, Value};
+use crate::config::{json_schema, Config};
+use crate::config_loader::{source_for, ConfigSource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    /// Accepted, but probably not what was meant.
+    Warning,
+}
+
+/// One problem with a configuration value, and where that value was set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Dotted key, with list indices, e.g. `io_policies[0].max_mb_per_sec`.
+    pub key: String,
+    pub message: String,
+    pub source: ConfigSource,
+    /// 1-based line in the source file, when the key could be found in it.
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}: {} ({}", severity, self.key, self.message, self.source)?;
+        if let Some(line) = self.line {
+            write!(f, ", line {}", line)?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Returned by [`crate::config_loader::ConfigLoader::load`] when any value is invalid;
+/// lists every problem found, warnings included.
+#[derive(Debug)]
+pub struct InvalidConfig(pub Vec<Diagnostic>);
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors = self.0.iter().filter(|d| d.severity == Severity::Error).count();
+        write!(f, "{} problem(s) in the configuration:", errors)?;
+        for diagnostic in &self.0 {
+            write!(f, "\n  {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+/// Checks merged configuration values: unknown keys (against [`json_schema`]), values of
+/// the wrong type, and the settings themselves, such as roots that do not exist or
+/// overlap. Every problem is reported rather than only the first; settings are checked
+/// with defaults standing in for keys that are unknown or of the wrong type.
+pub(crate) fn validate(values: &Value, sources: &BTreeMap<String, ConfigSource>) -> Vec<Diagnostic> {
+    let mut report = Report { sources, diagnostics: Vec::new() };
+    let schema = json_schema();
+    check_keys(values, &schema, &schema, "", &mut report);
+
+    let defaults = serde_json::to_value(Config::default()).expect("defaults serialize");
+    let mut known = Map::new();
+    for (key, value) in values.as_object().into_iter().flatten() {
+        if defaults.get(key).is_some() {
+            let before = report.diagnostics.len();
+            check_type(&defaults, &[key.as_str()], value, &mut report);
+            if report.diagnostics.len() == before {
+                known.insert(key.clone(), value.clone());
+            }
+        }
+    }
+
+    let mut merged = defaults;
+    merged.as_object_mut().expect("defaults are a table").extend(known);
+    match serde_json::from_value::<Config>(merged) {
+        Ok(config) => check_settings(&config, &mut report),
+        Err(e) => report.error("", e.to_string()),
+    }
+    report.diagnostics
+}
+
+struct Report<'a> {
+    sources: &'a BTreeMap<String, ConfigSource>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Report<'_> {
+    fn push(&mut self, severity: Severity, key: &str, message: String) {
+        let source = source_for(self.sources, &strip_indices(key)).clone();
+        let line = locate(&source, key);
+        self.diagnostics.push(Diagnostic { severity, key: key.to_string(), message, source, line });
+    }
+
+    fn error(&mut self, key: &str, message: String) {
+        self.push(Severity::Error, key, message);
+    }
+
+    fn warning(&mut self, key: &str, message: String) {
+        self.push(Severity::Warning, key, message);
+    }
+}
+
+/// Flags keys the schema does not know, descending into tables and lists of tables.
+fn check_keys(value: &Value, schema: &Value, root: &Value, path: &str, report: &mut Report) {
+    let Some(schema) = object_schema(schema, root) else { return };
+    match value {
+        Value::Object(map) => {
+            let Some(properties) = schema.get("properties").and_then(Value::as_object) else { return };
+            for (key, child) in map {
+                let child_path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                match properties.get(key) {
+                    Some(child_schema) => check_keys(child, child_schema, root, &child_path, report),
+                    None => {
+                        let hint = closest(key, properties.keys()).map(|k| format!("; did you mean '{}'?", k)).unwrap_or_default();
+                        report.error(&child_path, format!("unknown key{}", hint));
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_keys(item, item_schema, root, &format!("{}[{}]", path, i), report);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Follows `$ref` and the non-null branch of `anyOf` to the schema describing a value.
+fn object_schema<'a>(schema: &'a Value, root: &'a Value) -> Option<&'a Value> {
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let name = reference.strip_prefix("#/$defs/")?;
+        return object_schema(root.get("$defs")?.get(name)?, root);
+    }
+    if let Some(branches) = schema.get("anyOf").and_then(Value::as_array) {
+        return branches.iter().find(|b| b.get("type") != Some(&Value::from("null"))).and_then(|b| object_schema(b, root));
+    }
+    Some(schema)
+}
+
+/// Tries `value` at `path` with defaults everywhere else, narrowing a failure down to the
+/// innermost key that causes it.
+fn check_type(defaults: &Value, path: &[&str], value: &Value, report: &mut Report) {
+    let mut probe = defaults.clone();
+    let mut slot = &mut probe;
+    for key in path {
+        slot = &mut slot[*key];
+    }
+    let default = std::mem::replace(slot, value.clone());
+    let Err(e) = serde_json::from_value::<Config>(probe) else { return };
+
+    if let (Value::Object(map), Value::Object(_)) = (value, &default) {
+        let before = report.diagnostics.len();
+        for (key, child) in map {
+            let mut child_path = path.to_vec();
+            child_path.push(key);
+            check_type(defaults, &child_path, child, report);
+        }
+        if report.diagnostics.len() > before {
+            return;
+        }
+    }
+    report.error(&path.join("."), e.to_string());
+}
+
+fn check_settings(config: &Config, report: &mut Report) {
+    if config.root_paths.is_empty() {
+        report.warning("root_paths", "no roots configured, so there is nothing to scan".into());
+    }
+    for (key, roots) in [("root_paths", &config.root_paths), ("reference_paths", &config.reference_paths)] {
+        for (i, root) in roots.iter().enumerate() {
+            if !root.is_dir() {
+                let problem = if root.exists() { "is not a directory" } else { "does not exist" };
+                report.error(&format!("{}[{}]", key, i), format!("'{}' {}", root.display(), problem));
+            }
+        }
+    }
+
+    // The walk does not skip what it has seen, so nested roots would list files twice.
+    let walk_roots: Vec<(String, &PathBuf)> = config.root_paths.iter().enumerate().map(|(i, r)| (format!("root_paths[{}]", i), r))
+        .chain(config.reference_paths.iter().enumerate().map(|(i, r)| (format!("reference_paths[{}]", i), r)))
+        .collect();
+    for (i, (key, root)) in walk_roots.iter().enumerate() {
+        let outer = walk_roots.iter().enumerate().find(|(j, (_, other))| *j != i && root.starts_with(other) && (root != other || *j < i));
+        if let Some((_, (other_key, other))) = outer {
+            let relation = if root == other { "is the same directory as" } else { "is inside" };
+            report.error(key, format!("'{}' {} '{}' ({}), so its files would be scanned twice", root.display(), relation, other.display(), other_key));
+        }
+    }
+
+    if let (Some(min), Some(max)) = (config.min_file_size, config.max_file_size)
+        && min > max
+    {
+        report.error("min_file_size", format!("{} is larger than max_file_size ({}), so no file can match", min, max));
+    }
+    for (i, pattern) in config.exclude_patterns.iter().enumerate() {
+        if let Err(e) = Pattern::new(pattern) {
+            report.error(&format!("exclude_patterns[{}]", i), format!("'{}' is not a valid pattern: {}", pattern, e));
+        }
+    }
+    if config.threads == Some(0) {
+        report.error("threads", "must be at least 1, or null for one per CPU".into());
+    }
+    if config.read.buffer_size == 0 {
+        report.error("read.buffer_size", "must be at least 1".into());
+    }
+    if let Some(sampling) = &config.sampling {
+        if sampling.block_size == 0 {
+            report.error("sampling.block_size", "must be at least 1".into());
+        }
+        if sampling.blocks == 0 {
+            report.error("sampling.blocks", "must be at least 1, or set sampling to null to turn it off".into());
+        }
+    }
+    if config.plugins.is_empty() {
+        report.error("plugins", "at least one plugin is required".into());
+    }
+    for (i, plugin) in config.plugins.iter().enumerate() {
+        for (key, problem) in plugin.check() {
+            report.error(&format!("plugins[{}].{}", i, key), problem);
+        }
+    }
+    for (i, policy) in config.io_policies.iter().enumerate() {
+        if !walk_roots.iter().any(|(_, root)| policy.root.starts_with(root) || root.starts_with(&policy.root)) {
+            report.warning(&format!("io_policies[{}].root", i), format!("'{}' is outside every scanned root, so the policy never applies", policy.root.display()));
+        }
+        if policy.readers_per_device == Some(0) {
+            report.error(&format!("io_policies[{}].readers_per_device", i), "must be at least 1".into());
+        }
+        if policy.max_mb_per_sec.is_some_and(|rate| rate.is_nan() || rate <= 0.0) {
+            report.error(&format!("io_policies[{}].max_mb_per_sec", i), "must be greater than 0".into());
+        }
+    }
+}
+
+fn strip_indices(key: &str) -> String {
+    let mut stripped = String::with_capacity(key.len());
+    let mut depth = 0;
+    for c in key.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            _ if depth == 0 => stripped.push(c),
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Best-effort line of `key` in the file it came from: the first line assigning its last
+/// segment, as `"name":` in JSON (anywhere on the line) or `name =` in TOML.
+fn locate(source: &ConfigSource, key: &str) -> Option<usize> {
+    let (ConfigSource::System(path) | ConfigSource::User(path) | ConfigSource::Project(path)) = source else { return None };
+    let text = fs::read_to_string(path).ok()?;
+    let stripped = strip_indices(key);
+    let name = stripped.rsplit('.').next()?;
+    let json_key = format!("\"{}\"", name);
+    text.lines()
+        .map(str::trim_start)
+        .position(|line| {
+            let assigned = |rest: &str| rest.trim_start().starts_with([':', '=']);
+            line.match_indices(&json_key).any(|(at, _)| assigned(&line[at + json_key.len()..])) || line.strip_prefix(name).is_some_and(assigned)
+        })
+        .map(|i| i + 1)
+}
+
+/// The known key nearest to a mistyped one, if any is close enough to be a typo.
+fn closest<'a>(key: &str, known: impl Iterator<Item = &'a String>) -> Option<&'a str> {
+    known
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2.max(key.len() / 4))
+        .min()
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            current.push((previous[j] + usize::from(ca != *cb)).min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_loader::ConfigLoader;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_all_problems_are_reported_with_their_location() {
+        let dir = tempdir().unwrap();
+        let photos = dir.path().join("photos");
+        fs::create_dir_all(photos.join("2024")).unwrap();
+        let config = format!(r#"{{
+  "root_paths": ["{photos}", "{photos}/2024", "{missing}"],
+  "min_file_size": 100,
+  "max_file_size": 10,
+  "exclude_patterns": ["[unclosed"],
+  "skip_hiden": true,
+  "read": {{ "buffer_size": "large" }}
+}}"#, photos = photos.display(), missing = dir.path().join("missing").display());
+        fs::write(dir.path().join("dedup.json"), config).unwrap();
+        let loader = || ConfigLoader::new().with_locations(None, None, Some(dir.path().to_path_buf()));
+
+        let error = loader().with_env([("DEDUP_THREADS".to_string(), "0".to_string())]).load().unwrap_err();
+        let invalid = error.downcast_ref::<InvalidConfig>().unwrap();
+        // Unknown keys and type errors come first, then the settings, checked in the same round.
+        let keys: Vec<&str> = invalid.0.iter().map(|d| d.key.as_str()).collect();
+        assert_eq!(keys, vec!["skip_hiden", "read.buffer_size", "root_paths[2]", "root_paths[1]", "min_file_size", "exclude_patterns[0]", "threads"]);
+        assert!(invalid.0[0].message.contains("did you mean 'skip_hidden'?"));
+        assert_eq!(invalid.0[0].source, ConfigSource::Project(dir.path().join("dedup.json")));
+        assert_eq!(invalid.0[0].line, Some(6));
+        assert_eq!(invalid.0[1].line, Some(7));
+        assert!(invalid.0[3].message.contains("scanned twice"));
+        assert_eq!(invalid.0[4].line, Some(3));
+        assert_eq!(invalid.0[6].source, ConfigSource::Environment("DEDUP_THREADS".to_string()));
+    }
+
+    #[test]
+    fn test_plugins_must_exist_and_be_in_range() {
+        let dir = tempdir().unwrap();
+        let load = |plugins: &str| {
+            fs::write(dir.path().join("dedup.json"), format!(r#"{{ "root_paths": ["{}"], "plugins": {} }}"#, dir.path().display(), plugins)).unwrap();
+            let error = ConfigLoader::new().with_locations(None, None, Some(dir.path().to_path_buf())).with_env([]).load().unwrap_err();
+            error.downcast::<InvalidConfig>().unwrap().0
+        };
+
+        let unknown = load(r#"[{ "plugin": "minhash" }]"#);
+        assert_eq!(unknown[0].key, "plugins");
+        assert!(unknown[0].message.contains("unknown variant `minhash`, expected `full_hash` or `piece_wise`"), "{}", unknown[0].message);
+
+        let out_of_range = load(r#"[{ "plugin": "full_hash", "buffer_size": 0 }]"#);
+        assert_eq!(out_of_range[0].key, "plugins[0].buffer_size");
+        assert_eq!(out_of_range[0].line, Some(1));
+    }
+
+    #[test]
+    fn test_generated_schema_is_up_to_date() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.schema.json");
+        let committed: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(committed, json_schema(), "regenerate with `dedup-core --print-schema > config.schema.json`");
+    }
+}
@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::{self, Read};
+use crate::cancel::CancellationToken;
+use crate::error::{ScanError, ScanStage};
+use crate::models::FileMetadata;
+
+/// Files compared in one sweep, including the reference; keeps well under typical fd limits.
+const MAX_OPEN_FILES: usize = 64;
+
+/// Result of checking one hash group byte for byte.
+#[derive(Debug, Default)]
+pub struct VerifiedGroup {
+    /// Subsets whose contents are identical; singletons are dropped.
+    pub groups: Vec<Vec<FileMetadata>>,
+    pub errors: Vec<ScanError>,
+}
+
+/// Compares the members of `files` byte for byte and splits them into identical subsets.
+///
+/// The first remaining file serves as the reference and is read in lockstep with up to
+/// [`MAX_OPEN_FILES`] others; a file leaves the sweep at its first differing chunk, and the
+/// sweep ends early once nothing is left to compare. Files that differed are verified again
+/// among themselves. Returns `None` if cancelled before the group was settled.
+pub fn verify_group(
+    files: Vec<FileMetadata>,
+    buffer_size: usize,
+    cancel: &CancellationToken,
+    on_read: &dyn Fn(u64),
+) -> Option<VerifiedGroup> {
+    let mut result = VerifiedGroup::default();
+    let mut remaining = files;
+
+    while remaining.len() > 1 {
+        let reference = remaining.remove(0);
+        let mut identical = vec![reference.clone()];
+        let mut differing = Vec::new();
+        let mut errors = Vec::new();
+
+        for batch in remaining.chunks(MAX_OPEN_FILES - 1) {
+            match compare_batch(&reference, batch, buffer_size, cancel, on_read) {
+                Ok(outcome) => {
+                    identical.extend(outcome.identical);
+                    differing.extend(outcome.differing);
+                    errors.extend(outcome.errors);
+                }
+                Err(_) if cancel.is_cancelled() => return None,
+                Err(e) => {
+                    // The reference is unreadable: report it and let the next file take over.
+                    // Everything learned against it is dropped, so the files of earlier
+                    // batches are verified (and their errors reported) once, in the next sweep.
+                    errors = vec![ScanError::from_io(&reference.path, ScanStage::Verification, &e)];
+                    identical.clear();
+                    differing = remaining.clone();
+                    break;
+                }
+            }
+        }
+        result.errors.extend(errors);
+
+        if identical.len() > 1 {
+            result.groups.push(identical);
+        }
+        remaining = differing;
+    }
+
+    Some(result)
+}
+
+struct BatchOutcome {
+    identical: Vec<FileMetadata>,
+    differing: Vec<FileMetadata>,
+    errors: Vec<ScanError>,
+}
+
+/// Reads `reference` and `others` side by side. Errors on the reference (including
+/// cancellation, as `Interrupted`) abort the batch; errors on the others are collected.
+fn compare_batch(
+    reference: &FileMetadata,
+    others: &[FileMetadata],
+    buffer_size: usize,
+    cancel: &CancellationToken,
+    on_read: &dyn Fn(u64),
+) -> io::Result<BatchOutcome> {
+    let mut outcome = BatchOutcome { identical: Vec::new(), differing: Vec::new(), errors: Vec::new() };
+    let mut reference_file = File::open(&reference.path)?;
+    let mut active = Vec::with_capacity(others.len());
+    for other in others {
+        match File::open(&other.path) {
+            Ok(file) => active.push((other, file)),
+            Err(e) => outcome.errors.push(ScanError::from_io(&other.path, ScanStage::Verification, &e)),
+        }
+    }
+
+    let mut expected = vec![0u8; buffer_size];
+    let mut actual = vec![0u8; buffer_size];
+    loop {
+        if cancel.is_cancelled() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "verification cancelled"));
+        }
+        if active.is_empty() {
+            break;
+        }
+        let length = fill(&mut reference_file, &mut expected)?;
+        on_read(length as u64);
+
+        active.retain_mut(|(other, file)| match fill(file, &mut actual) {
+            Ok(n) => {
+                on_read(n as u64);
+                if n == length && actual[..n] == expected[..length] {
+                    return true;
+                }
+                outcome.differing.push((*other).clone());
+                false
+            }
+            Err(e) => {
+                outcome.errors.push(ScanError::from_io(&other.path, ScanStage::Verification, &e));
+                false
+            }
+        });
+
+        if length == 0 {
+            break;
+        }
+    }
+
+    outcome.identical = active.into_iter().map(|(other, _)| other.clone()).collect();
+    Ok(outcome)
+}
+
+/// Reads until `buffer` is full or the file ends, so chunks line up across files.
+fn fill(file: &mut File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn metadata(path: &Path) -> FileMetadata {
+        FileMetadata { path: path.to_path_buf(), size: fs::metadata(path).map(|m| m.len()).unwrap_or(0), hash: None, modified: None, created: None }
+    }
+
+    #[test]
+    fn test_verify_splits_on_mismatch() {
+        let dir = tempdir().unwrap();
+        let content = vec![1u8; 10_000];
+        let mut changed = content.clone();
+        changed[9_000] = 2;
+        for (name, data) in [("a", &content), ("b", &changed), ("c", &content), ("d", &changed), ("e", &content)] {
+            fs::write(dir.path().join(name), data).unwrap();
+        }
+        let mut files: Vec<FileMetadata> = ["a", "b", "c", "d", "e"].iter().map(|n| metadata(&dir.path().join(n))).collect();
+        let mut gone = metadata(&dir.path().join("a"));
+        gone.path = dir.path().join("gone");
+        files.push(gone);
+
+        let verified = verify_group(files, 4096, &CancellationToken::new(), &|_| {}).unwrap();
+        let names: Vec<Vec<String>> = verified.groups.iter()
+            .map(|g| g.iter().map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned()).collect())
+            .collect();
+        assert_eq!(names, vec![vec!["a", "c", "e"], vec!["b", "d"]]);
+        assert_eq!(verified.errors.len(), 1);
+        assert_eq!(verified.errors[0].stage, ScanStage::Verification);
+    }
+
+    #[test]
+    fn test_reference_lost_between_batches_reports_each_error_once() {
+        let dir = tempdir().unwrap();
+        let mut files = Vec::new();
+        for i in 0..MAX_OPEN_FILES + 5 {
+            let path = dir.path().join(format!("{i:03}"));
+            fs::write(&path, "same").unwrap();
+            files.push(metadata(&path));
+        }
+        let mut gone = metadata(&files[1].path);
+        gone.path = dir.path().join("gone");
+        files.insert(2, gone);
+
+        // Ссылочный файл уже открыт в первой пачке, а вторая открыть его не сможет
+        let reference = files[0].path.clone();
+        let verified = verify_group(files, 4096, &CancellationToken::new(), &|_| {
+            let _ = fs::remove_file(&reference);
+        })
+        .unwrap();
+
+        let mut failed: Vec<_> = verified.errors.iter().map(|e| e.path.file_name().unwrap().to_string_lossy().into_owned()).collect();
+        failed.sort();
+        assert_eq!(failed, ["000", "gone"]);
+        assert_eq!(verified.groups.len(), 1);
+        assert_eq!(verified.groups[0].len(), MAX_OPEN_FILES + 4);
+    }
+
+    #[test]
+    fn test_verify_cancelled() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a"), "same").unwrap();
+        fs::write(dir.path().join("b"), "same").unwrap();
+        let files = ["a", "b"].iter().map(|n| metadata(&dir.path().join(n))).collect();
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(verify_group(files, 4096, &cancel, &|_| {}).is_none());
+    }
+}
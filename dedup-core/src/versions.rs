@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use crate::error::{ScanError, ScanStage};
+use crate::models::FileMetadata;
+use crate::similarity::{self, BlockSignature};
+
+/// What changed from one version to the next, in bytes, at block granularity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionStep {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub similarity: f64,
+    /// Content of `from` found again in `to`, possibly moved.
+    pub kept_bytes: u64,
+    /// New content in `to` that does not replace anything.
+    pub added_bytes: u64,
+    /// Content of `from` dropped without replacement.
+    pub removed_bytes: u64,
+    /// Content of `from` replaced in place: a stretch between two kept parts whose
+    /// counterpart in `to`, between the same two parts, is different.
+    pub changed_bytes: u64,
+}
+
+/// Likely order in which a group of similar files was derived from one another.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VersionChain {
+    /// Oldest first.
+    pub files: Vec<PathBuf>,
+    /// `steps[i]` leads from `files[i]` to `files[i + 1]`.
+    pub steps: Vec<VersionStep>,
+}
+
+impl VersionChain {
+    pub fn latest(&self) -> Option<&PathBuf> {
+        self.files.last()
+    }
+}
+
+/// Orders `files` into a version chain.
+///
+/// The chain starts at the oldest file and always moves to the oldest remaining one;
+/// files with the same or no modification time are ordered by similarity to the
+/// previous version, so a chain of copies with reset timestamps still comes out right.
+pub fn build_chain(files: &[FileMetadata], block_size: usize) -> Result<VersionChain, ScanError> {
+    let signatures = files
+        .iter()
+        .map(|f| similarity::block_signature(&f.path, block_size).map_err(|e| ScanError::from_io(&f.path, ScanStage::Hashing, &e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut remaining: Vec<usize> = (0..files.len()).collect();
+    remaining.sort_by(|&a, &b| time_key(files[a].modified).cmp(&time_key(files[b].modified)).then_with(|| files[a].path.cmp(&files[b].path)));
+    let mut order: Vec<usize> = Vec::with_capacity(files.len());
+    let mut steps = Vec::new();
+    while !remaining.is_empty() {
+        let oldest = time_key(files[remaining[0]].modified);
+        let tied = remaining.iter().take_while(|&&i| time_key(files[i].modified) == oldest).count();
+        let (position, step) = match order.last() {
+            None => (0, None),
+            Some(&previous) => remaining[..tied]
+                .iter()
+                .enumerate()
+                .map(|(position, &next)| (position, compare(&signatures[previous], &signatures[next])))
+                .fold(None, |best: Option<(usize, VersionStep)>, (position, step)| match best {
+                    Some((_, ref b)) if b.similarity >= step.similarity => best,
+                    _ => Some((position, step)),
+                })
+                .map(|(position, step)| (position, Some(step)))
+                .unwrap(),
+        };
+        order.push(remaining.remove(position));
+        steps.extend(step);
+    }
+
+    Ok(VersionChain {
+        files: order.into_iter().map(|i| files[i].path.clone()).collect(),
+        steps,
+    })
+}
+
+// Файлы без времени изменения идут после всех датированных
+fn time_key(modified: Option<SystemTime>) -> (bool, Option<SystemTime>) {
+    (modified.is_none(), modified)
+}
+
+fn compare(from: &BlockSignature, to: &BlockSignature) -> VersionStep {
+    let pair = similarity::compare_signatures(from, to);
+    let kept_bytes = pair.shared_bytes();
+    let to_matched: u64 = pair.ranges.iter().map(|r| r.len.min(to.size.saturating_sub(r.right_offset))).sum();
+
+    // Диапазоны идут по порядку левого файла; правка «на месте» — промежуток между двумя
+    // соседними диапазонами, которые и в правом файле идут в том же порядке
+    let changed_bytes: u64 = pair
+        .ranges
+        .windows(2)
+        .filter(|w| w[1].right_offset >= w[0].right_offset + w[0].len)
+        .map(|w| {
+            let left_gap = w[1].left_offset - (w[0].left_offset + w[0].len);
+            let right_gap = w[1].right_offset - (w[0].right_offset + w[0].len);
+            left_gap.min(right_gap)
+        })
+        .sum();
+    VersionStep {
+        from: from.path.clone(),
+        to: to.path.clone(),
+        similarity: pair.similarity,
+        kept_bytes,
+        added_bytes: to.size.saturating_sub(to_matched + changed_bytes),
+        removed_bytes: from.size.saturating_sub(kept_bytes + changed_bytes),
+        changed_bytes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    const BLOCK: usize = 512;
+
+    fn version(dir: &Path, name: &str, blocks: &[u8], modified: Option<u64>) -> FileMetadata {
+        let path = dir.join(name);
+        let data: Vec<u8> = blocks.iter().flat_map(|&b| vec![b; BLOCK]).collect();
+        std::fs::write(&path, &data).unwrap();
+        FileMetadata {
+            path,
+            size: data.len() as u64,
+            hash: None,
+            modified: modified.map(|s| SystemTime::UNIX_EPOCH + Duration::from_secs(s)),
+            created: None,
+        }
+    }
+
+    #[test]
+    fn test_orders_by_time_and_measures_each_step() {
+        let dir = tempdir().unwrap();
+        let files = vec![
+            version(dir.path(), "report_final", &[1, 2, 7, 4, 5], Some(200)),
+            version(dir.path(), "report_v1", &[1, 2, 3], Some(100)),
+            version(dir.path(), "report_final2", &[1, 2, 7, 5], Some(300)),
+        ];
+
+        let chain = build_chain(&files, BLOCK).unwrap();
+        let names: Vec<_> = chain.files.iter().map(|f| f.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["report_v1", "report_final", "report_final2"]);
+
+        let block = BLOCK as u64;
+        let first = &chain.steps[0];
+        assert_eq!((first.kept_bytes, first.changed_bytes, first.added_bytes, first.removed_bytes), (2 * block, 0, 3 * block, block));
+        let second = &chain.steps[1];
+        assert_eq!((second.kept_bytes, second.changed_bytes, second.added_bytes, second.removed_bytes), (4 * block, 0, 0, block));
+    }
+
+    #[test]
+    fn test_repeated_blocks_are_kept_once_and_edits_in_place_are_changes() {
+        let dir = tempdir().unwrap();
+        let files = vec![
+            version(dir.path(), "old", &[1, 1, 1, 1], Some(100)),
+            version(dir.path(), "new", &[1, 2], Some(200)),
+        ];
+        let chain = build_chain(&files, BLOCK).unwrap();
+        let step = &chain.steps[0];
+        let block = BLOCK as u64;
+        assert!(step.kept_bytes <= (4 * block).min(2 * block));
+        assert_eq!((step.kept_bytes, step.changed_bytes, step.added_bytes, step.removed_bytes), (block, 0, block, 3 * block));
+
+        let files = vec![
+            version(dir.path(), "a", &[1, 2, 3, 4], Some(100)),
+            version(dir.path(), "b", &[1, 9, 3, 4, 5], Some(200)),
+        ];
+        let step = &build_chain(&files, BLOCK).unwrap().steps[0];
+        assert_eq!((step.kept_bytes, step.changed_bytes, step.added_bytes, step.removed_bytes), (3 * block, block, block, 0));
+    }
+
+    #[test]
+    fn test_ties_and_missing_times_follow_similarity() {
+        let dir = tempdir().unwrap();
+        let files = vec![
+            version(dir.path(), "a", &[1, 2, 3, 4], Some(100)),
+            version(dir.path(), "c", &[1, 9, 9, 9], None),
+            version(dir.path(), "b", &[1, 2, 3, 9], None),
+        ];
+
+        let chain = build_chain(&files, BLOCK).unwrap();
+        let names: Vec<_> = chain.files.iter().map(|f| f.file_name().unwrap().to_str().unwrap()).collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(chain.latest(), Some(&dir.path().join("c")));
+    }
+}
@@ -0,0 +1,388 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use rayon::{Scope, ThreadPool, ThreadPoolBuildError, ThreadPoolBuilder};
+use crate::cancel::CancellationToken;
+use crate::config::ScanConfig;
+use crate::error::{ScanError, ScanErrorKind, ScanStage};
+use crate::events::EventBus;
+use crate::models::{ProgressUpdate, ScanStats};
+use crate::scanner::{self, ScanItem};
+
+/// Directory walker that reads every directory as its own task on a work-stealing pool.
+///
+/// Applies the same filters as the serial walk in [`crate::scanner`]: exclude patterns,
+/// hidden entries, `max_depth`, symlink following with loop detection, and the size limits.
+/// Entries arrive in no particular order.
+pub struct ParallelWalker {
+    config: Arc<ScanConfig>,
+    events: Arc<EventBus>,
+    cancel: CancellationToken,
+    pool: ThreadPool,
+}
+
+/// Hooks for tracking which directories still have to be read, used by checkpoints.
+///
+/// A directory is reported done only after all of its files have been emitted and all of
+/// its subdirectories queued, so "queued but not done" is exactly the work that remains.
+pub trait WalkObserver: Sync {
+    fn directory_queued(&self, dir: &Path, depth: usize);
+    fn directory_done(&self, dir: &Path);
+}
+
+struct WalkState<'a, F> {
+    config: &'a ScanConfig,
+    events: &'a EventBus,
+    cancel: &'a CancellationToken,
+    observer: Option<&'a dyn WalkObserver>,
+    sink: F,
+    stop: AtomicBool,
+    files_scanned: AtomicU64,
+    total_size: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// Canonical paths of the directories above the current one, used to detect symlink loops.
+struct Ancestor {
+    path: PathBuf,
+    parent: Option<Arc<Ancestor>>,
+}
+
+impl Ancestor {
+    fn contains(&self, path: &Path) -> bool {
+        let mut current = Some(self);
+        while let Some(ancestor) = current {
+            if ancestor.path == path {
+                return true;
+            }
+            current = ancestor.parent.as_deref();
+        }
+        false
+    }
+}
+
+impl ParallelWalker {
+    /// Builds the worker pool, sized by `config.threads`.
+    pub fn new(config: Arc<ScanConfig>, events: Arc<EventBus>, cancel: CancellationToken) -> Result<Self, ThreadPoolBuildError> {
+        let mut builder = ThreadPoolBuilder::new().thread_name(|i| format!("dedup-walk-{i}"));
+        if let Some(threads) = config.threads {
+            builder = builder.num_threads(threads);
+        }
+        let pool = builder.build()?;
+        Ok(ParallelWalker { config, events, cancel, pool })
+    }
+
+    /// Walks all roots, handing every file or error to `sink` until it returns `false`
+    /// or the walk is cancelled. Does not publish `Finished`; that is left to the caller,
+    /// which knows when it is done.
+    pub fn walk<F>(&self, sink: F) -> ScanStats
+    where
+        F: Fn(ScanItem) -> bool + Sync,
+    {
+        let roots: Vec<(PathBuf, usize)> = self.config.walk_roots().map(|root| (root.clone(), 0)).collect();
+        self.walk_from(&roots, sink, None)
+    }
+
+    /// Walks starting from arbitrary `(path, depth)` points instead of the configured roots,
+    /// e.g. the unfinished directories of a checkpoint. Starts are expected to be queued
+    /// with the observer already; they are reported done like any other directory.
+    pub fn walk_from<F>(&self, starts: &[(PathBuf, usize)], sink: F, observer: Option<&dyn WalkObserver>) -> ScanStats
+    where
+        F: Fn(ScanItem) -> bool + Sync,
+    {
+        let state = WalkState {
+            config: &self.config,
+            events: &self.events,
+            cancel: &self.cancel,
+            observer,
+            sink,
+            stop: AtomicBool::new(false),
+            files_scanned: AtomicU64::new(0),
+            total_size: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        };
+
+        self.pool.scope(|scope| {
+            for (start, depth) in starts {
+                let state = &state;
+                scope.spawn(move |scope| state.walk_start(scope, start, *depth));
+            }
+        });
+
+        let mut stats = ScanStats::new();
+        stats.files_scanned = state.files_scanned.into_inner();
+        stats.total_size = state.total_size.into_inner();
+        stats.errors = state.errors.into_inner();
+        stats
+    }
+}
+
+impl<'a, F> WalkState<'a, F>
+where
+    F: Fn(ScanItem) -> bool + Sync,
+{
+    fn emit(&self, item: ScanItem) {
+        if item.is_err() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if !(self.sink)(item) {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed) || self.cancel.is_cancelled()
+    }
+
+    fn walk_start<'s>(&'s self, scope: &Scope<'s>, start: &Path, depth: usize) {
+        if self.stopped() {
+            return;
+        }
+
+        if scanner::is_skipped(self.config, start, depth) {
+            return self.done(start);
+        }
+
+        // Like walkdir, a symlinked root is always followed.
+        let metadata = match fs::metadata(start) {
+            Ok(m) => m,
+            Err(e) => {
+                self.emit(Err(ScanError::from_io(start, ScanStage::Walk, &e)));
+                return self.done(start);
+            }
+        };
+
+        if metadata.is_dir() {
+            let ancestors = self.start_ancestors(start);
+            self.walk_dir(scope, start.to_path_buf(), depth, ancestors);
+        } else {
+            if metadata.is_file() {
+                self.visit_file(start.to_path_buf(), Ok(metadata));
+            }
+            self.done(start);
+        }
+    }
+
+    fn done(&self, dir: &Path) {
+        if let Some(observer) = self.observer {
+            observer.directory_done(dir);
+        }
+    }
+
+    fn walk_dir<'s>(&'s self, scope: &Scope<'s>, dir: PathBuf, depth: usize, ancestors: Option<Arc<Ancestor>>) {
+        if self.stopped() {
+            return;
+        }
+
+        let child_depth = depth + 1;
+        if child_depth > self.config.max_depth.unwrap_or(usize::MAX) {
+            return self.done(&dir);
+        }
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.emit(Err(ScanError::from_io(&dir, ScanStage::Walk, &e)));
+                return self.done(&dir);
+            }
+        };
+
+        for entry in entries {
+            if self.stopped() {
+                return;
+            }
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.emit(Err(ScanError::from_io(&dir, ScanStage::Walk, &e)));
+                    continue;
+                }
+            };
+            let path = entry.path();
+
+            if scanner::is_skipped(self.config, &path, child_depth) {
+                continue;
+            }
+
+            let file_type = match entry.file_type() {
+                Ok(t) => t,
+                Err(e) => {
+                    self.emit(Err(ScanError::from_io(&path, ScanStage::Walk, &e)));
+                    continue;
+                }
+            };
+
+            if file_type.is_symlink() {
+                if !self.config.follow_symlinks {
+                    continue;
+                }
+                match fs::metadata(&path) {
+                    Ok(target) if target.is_dir() => {
+                        self.descend(scope, path, child_depth, &ancestors)
+                    }
+                    Ok(target) if target.is_file() => self.visit_file(path, Ok(target)),
+                    Ok(_) => {}
+                    Err(e) => self.emit(Err(ScanError::from_io(&path, ScanStage::Walk, &e))),
+                }
+            } else if file_type.is_dir() {
+                self.descend(scope, path, child_depth, &ancestors);
+            } else if file_type.is_file() {
+                let metadata = entry.metadata()
+                    .map_err(|e| ScanError::from_io(&path, ScanStage::Metadata, &e));
+                self.visit_file(path, metadata);
+            }
+        }
+
+        self.done(&dir);
+    }
+
+    fn descend<'s>(&'s self, scope: &Scope<'s>, dir: PathBuf, depth: usize, ancestors: &Option<Arc<Ancestor>>) {
+        let ancestors = if self.config.follow_symlinks {
+            let canonical = match fs::canonicalize(&dir) {
+                Ok(path) => path,
+                Err(e) => return self.emit(Err(ScanError::from_io(&dir, ScanStage::Walk, &e))),
+            };
+            if ancestors.as_ref().is_some_and(|a| a.contains(&canonical)) {
+                return self.emit(Err(ScanError {
+                    message: format!("{} points to one of its ancestors", dir.display()),
+                    path: dir,
+                    kind: ScanErrorKind::SymlinkLoop,
+                    stage: ScanStage::Walk,
+                }));
+            }
+            Some(Arc::new(Ancestor { path: canonical, parent: ancestors.clone() }))
+        } else {
+            None
+        };
+
+        if let Some(observer) = self.observer {
+            observer.directory_queued(&dir, depth);
+        }
+        scope.spawn(move |scope| self.walk_dir(scope, dir, depth, ancestors));
+    }
+
+    /// Rebuilds the ancestor chain from the enclosing root down to `start`.
+    fn start_ancestors(&self, start: &Path) -> Option<Arc<Ancestor>> {
+        if !self.config.follow_symlinks {
+            return None;
+        }
+
+        let root = self.config.walk_roots().find(|root| start.starts_with(root));
+        let mut chain: Vec<&Path> = start.ancestors()
+            .take_while(|dir| root.is_some_and(|root| dir.starts_with(root)))
+            .collect();
+        if chain.is_empty() {
+            chain.push(start);
+        }
+
+        let mut parent = None;
+        for dir in chain.into_iter().rev() {
+            let path = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+            parent = Some(Arc::new(Ancestor { path, parent }));
+        }
+        parent
+    }
+
+    fn visit_file(&self, path: PathBuf, metadata: Result<fs::Metadata, ScanError>) {
+        let files_scanned = self.files_scanned.fetch_add(1, Ordering::Relaxed) + 1;
+        self.events.publish(ProgressUpdate::Scanning {
+            current_path: path.clone(),
+            files_scanned,
+        });
+
+        match scanner::create_metadata(self.config, path, metadata) {
+            Ok(Some(file)) => {
+                self.total_size.fetch_add(file.size, Ordering::Relaxed);
+                self.emit(Ok(file));
+            }
+            Ok(None) => {}
+            Err(e) => self.emit(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::models::HashAlgorithm;
+    use crate::scanner::Scanner;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn config(root: &Path, exclude: &[&str], max_depth: Option<usize>, follow_symlinks: bool) -> ScanConfig {
+        ScanConfig::build(Config {
+            root_paths: vec![root.to_path_buf()],
+            min_file_size: Some(1),
+            max_file_size: None,
+            follow_symlinks,
+            exclude_patterns: exclude.iter().map(|p| p.to_string()).collect(),
+            max_depth,
+            skip_hidden: true,
+            threads: Some(4),
+            sort_output: true,
+            io_policies: vec![],
+            read: Default::default(),
+            sampling: None,
+            verify_bytes: false,
+            reference_paths: vec![],
+            collapse_directories: false,
+            algorithm: HashAlgorithm::SHA256,
+            plugins: vec![],
+        }).unwrap()
+    }
+
+    fn build_tree(root: &Path) {
+        for dir in ["a/b/c", "a/.hidden", "skip_me", "d"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["top.txt", "a/one.txt", "a/b/two.txt", "a/b/c/three.txt", "a/.hidden/secret.txt", "skip_me/x.txt", "d/four.log", "a/.dotfile"] {
+            fs::write(root.join(file), file).unwrap();
+        }
+        fs::write(root.join("d/empty.txt"), "").unwrap();
+    }
+
+    #[test]
+    fn test_parallel_walk_matches_serial_walk() {
+        let dir = tempdir().unwrap();
+        build_tree(dir.path());
+        let exclude = format!("{}/skip_me", dir.path().display());
+
+        for max_depth in [None, Some(0), Some(1), Some(2)] {
+            let scanner = Scanner::new(config(dir.path(), &[&exclude], max_depth, false), None);
+            let serial = scanner.scan().unwrap();
+            let parallel = scanner.scan_parallel().unwrap();
+
+            let serial_paths: Vec<_> = serial.files.iter().map(|f| &f.path).collect();
+            let parallel_paths: Vec<_> = parallel.files.iter().map(|f| &f.path).collect();
+            assert_eq!(serial_paths, parallel_paths, "max_depth {:?}", max_depth);
+            assert_eq!(serial.stats.total_size, parallel.stats.total_size);
+        }
+
+        let scanner = Scanner::new(config(dir.path(), &[&exclude], None, false), None);
+        let names: Vec<_> = scanner.scan_parallel().unwrap().files.iter()
+            .map(|f| f.path.strip_prefix(dir.path()).unwrap().to_path_buf())
+            .collect();
+        let expected: Vec<PathBuf> = ["a/b/c/three.txt", "a/b/two.txt", "a/one.txt", "d/four.log", "top.txt"]
+            .iter().map(PathBuf::from).collect();
+        assert_eq!(names, expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_parallel_walk_detects_symlink_loops() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("a/file.txt"), "content").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a/loop")).unwrap();
+
+        let scanner = Scanner::new(config(dir.path(), &[], None, true), None);
+        let result = scanner.scan_parallel().unwrap();
+
+        assert_eq!(result.files.len(), 1);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.errors[0].kind, ScanErrorKind::SymlinkLoop);
+    }
+}
@@ -166,30 +166,63 @@ impl DuplicateFinder {
         result
     }
 
+    pub fn find_with_config(&self, config: &config::DuplicateConfig) -> Result<Vec<Vec<PathBuf>>> {
+        let files: Vec<PathBuf> = WalkDir::new(&self.input_dir)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_file())
+            .map(|e| e.into_path())
+            .collect();
+        let result = config.pipeline().map_err(|e| anyhow::anyhow!("{}", e))?.execute(&files);
+        Ok(result
+            .duplicate_files
+            .into_iter()
+            .map(|group| group.into_iter().map(|f| f.path).collect())
+            .collect())
+    }
+
     // pub fn find_partial_duplicates() -> () {
     //     ()
     // }
 }
 
 pub mod config {
+    use dedup_core::models::HashAlgorithm;
+    use dedup_core::plugins::PluginPipeline;
     use serde::{Deserialize, Serialize};
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub use dedup_core::plugins::{FullHashSettings, PieceWiseSettings, PluginConfig};
+    pub use dedup_core::similarity::BlockSimilarity;
+
+    /// Plugins in the order they run; each sees only files the previous ones left unique.
+    /// The same `plugins` and `algorithm` as in the dedup-core configuration.
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[serde(default, deny_unknown_fields)]
     pub struct DuplicateConfig {
-        pub enabled_plugins: Vec<String>,
-        pub case_sensitive: bool,
-        pub min_length: usize,
+        pub plugins: Vec<PluginConfig>,
+        pub algorithm: HashAlgorithm,
     }
 
     impl Default for DuplicateConfig {
         fn default() -> Self {
+            let defaults = dedup_core::config::Config::default();
             Self {
-                enabled_plugins: vec!["uppercase".to_string(), "reverse".to_string()],
-                case_sensitive: true,
-                min_length: 1,
+                plugins: defaults.plugins,
+                algorithm: defaults.algorithm,
             }
         }
     }
+
+    impl DuplicateConfig {
+        /// Every out-of-range setting as `plugins[i].key: problem`, joined into one message.
+        pub fn validate(&self) -> Result<(), String> {
+            self.pipeline().map(|_| ())
+        }
+
+        pub(crate) fn pipeline(&self) -> Result<PluginPipeline, String> {
+            PluginPipeline::from_config(&self.plugins, self.algorithm)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -364,6 +397,32 @@ mod tests {
         assert_eq!(normalized[0].len(), 3);
     }
 
+    #[test]
+    fn test_find_with_config() {
+        let temp_dir = tempdir().unwrap();
+        let input_dir = temp_dir.path();
+        let test_files: &[(&str, &[u8])] = &[
+            ("file1.txt", b"identical content".as_slice()),
+            ("file2.txt", b"identical content".as_slice()),
+            ("file3.txt", b"different content".as_slice()),
+        ];
+        create_test_files(input_dir, &test_files).unwrap();
+        let finder =
+            DuplicateFinder::new(input_dir.to_str().unwrap(), input_dir.to_str().unwrap()).unwrap();
+
+        let duplicates = finder.find_with_config(&config::DuplicateConfig::default()).unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].len(), 2);
+
+        let settings = config::FullHashSettings { buffer_size: 0 };
+        let bad = config::DuplicateConfig {
+            plugins: vec![config::PluginConfig::FullHash(settings)],
+            ..Default::default()
+        };
+        let error = bad.validate().unwrap_err();
+        assert!(error.starts_with("plugins[0].buffer_size"), "{}", error);
+    }
+
     // #[test]
     // fn test_find_partial_duplicates() {
     //     ()
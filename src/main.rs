@@ -1,42 +1,22 @@
 use file_deduplicator::{DuplicateFinder, file_generators};
 
-use serde::Deserialize;
-use std::error::Error;
-use std::path::{Path, PathBuf};
+use dedup_core::config_loader::ConfigLoader;
+use std::path::PathBuf;
 use std::{env, fs};
 
-const CONFIG_FILE: &str = "conf.json";
-
-#[derive(Debug, Deserialize)]
-struct Config {
-    target_dir: String,
-}
-
-fn read_config() -> Result<Config, Box<dyn Error>> {
-    let config_path = Path::new(CONFIG_FILE);
-    if !config_path.exists() {
-        return Err(format!("Config file '{}' not found. Please make sure it exists in the same directory as the program.", CONFIG_FILE).into());
-    }
-
-    let config_data = fs::read_to_string(CONFIG_FILE)
-        .map_err(|e| format!("Failed to read config file '{}': {}", CONFIG_FILE, e))?;
-
-    let config: Config = serde_json::from_str(&config_data)
-        .map_err(|e| format!("Invalid JSON in config file '{}': {}", CONFIG_FILE, e))?;
-
-    Ok(config)
-}
-
 fn main() {
     run_program().expect("TODO: panic message");
 }
 
 fn run_program() -> Result<(), Box<dyn std::error::Error>> {
     // file_generators::generate_test_files()?;
-    let config_data = read_config()?;
-    println!("Config file loaded successfully");
+    // Same layered configuration as the dedup-core binary; the first root is the target.
+    let loaded = ConfigLoader::new().load()?;
+    for file in &loaded.files {
+        println!("Using {}", file);
+    }
 
-    let input_dir = Path::new(&config_data.target_dir);
+    let input_dir = loaded.config.root_paths.first().ok_or("No root_paths configured")?;
     println!("Target directory: {}", input_dir.display());
 
     if !input_dir.exists() {
@@ -1,5 +1,5 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::hash::{Hash, Hasher};
 use std::collections::hash_map::DefaultHasher;
 
@@ -74,34 +74,36 @@ where
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct PluginResult {
     unique_files: Vec<ResultFileInfo>,
     duplicate_files: Vec<Vec<ResultFileInfo>>,
 }
 
 // Логика: у каждого плагина есть способ как представлять файл в виде хеша и как сравнивать эти представления чтобы получить результат формата PluginResult 
-trait IDeduplicatorPlugin {
+// Плагины создаются по имени из конфигурации, поэтому трейт должен быть object-safe
+trait IDeduplicatorPlugin: Send + Sync {
     fn name(&self) -> &str;
-    fn exec<P: AsRef<Path>>(
+    fn exec(
         &self,
-        file_list: &[P],
+        file_list: &[PathBuf],
     ) -> Result<PluginResult, Box<dyn std::error::Error>>;
 }
 
 trait IPluginPipeline {
-    fn add_plugin<T: IDeduplicatorPlugin>(&mut self, plugin: &T) -> Self;
+    fn add_plugin(&mut self, plugin: Box<dyn IDeduplicatorPlugin>);
 
-    fn remove_plugin(&mut self, plugin_name: String) -> Self;
+    fn remove_plugin(&mut self, plugin_name: &str);
 
-    fn list_plugins(&self);
+    fn list_plugins(&self) -> Vec<&str>;
 
     fn clear_list_plugins(&mut self);
 
-    fn execute(&self, file_list: &Vec<&str>) -> Result<PluginResult, Box<dyn std::error::Error>>;
+    fn execute(&self, file_list: &[PathBuf]) -> Result<PluginResult, Box<dyn std::error::Error>>;
 }
 
 
+
 pub fn compute_md5(data: &[u8]) -> String {
     use md5::compute;
     format!("{:x}", compute(data))
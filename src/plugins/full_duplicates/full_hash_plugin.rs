@@ -5,7 +5,7 @@ use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, Read};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct FullHashPlugin;
 
@@ -14,9 +14,9 @@ impl IDeduplicatorPlugin for FullHashPlugin {
         "full_hash"
     }
 
-    fn exec<P: AsRef<Path>>(
+    fn exec(
         &self,
-        file_list: &[P],
+        file_list: &[PathBuf],
     ) -> Result<PluginResult, Box<dyn std::error::Error>> {
         let mut unique_files: Vec<ResultFileInfo> = Vec::new();
         let mut duplicate_files: Vec<Vec<ResultFileInfo>> = Vec::new();